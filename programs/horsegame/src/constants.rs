@@ -3,15 +3,43 @@ pub const PLAYER_SEED: &[u8] = b"player";
 pub const STAKING_VAULT_SEED: &[u8] = b"staking_vault";
 pub const SOL_REWARDS_WALLET_SEED: &[u8] = b"sol_rewards_wallet";
 pub const REWARDS_VAULT_SEED: &[u8] = b"rewards_vault";
+pub const GAMBLE_BANKROLL_SEED: &[u8] = b"gamble_bankroll";
+pub const RACE_EVENT_SEED: &[u8] = b"race_event";
+pub const HORSE_NFT_SEED: &[u8] = b"horse_nft";
+pub const LISTING_SEED: &[u8] = b"listing";
+pub const EMISSION_SCHEDULE_SEED: &[u8] = b"emission_schedule";
+pub const SPEED_HISTORY_SEED: &[u8] = b"speed_history";
+pub const PENDING_AUTHORITY_SEED: &[u8] = b"pending_authority";
+pub const PENDING_PARAMETER_SEED: &[u8] = b"pending_parameter";
+pub const STABLE_TIERS_SEED: &[u8] = b"stable_tiers";
+pub const HORSE_CATALOG_SEED: &[u8] = b"horse_catalog";
+pub const PACK_TYPE_SEED: &[u8] = b"pack_type";
+pub const BREEDING_CONFIG_SEED: &[u8] = b"breeding_config";
+pub const FOAL_RECORD_SEED: &[u8] = b"foal_record";
+pub const RANDOMNESS_ORACLES_SEED: &[u8] = b"randomness_oracles";
+pub const BREEDER_SEED: &[u8] = b"breeder";
+pub const ORACLE_REQUEST_SEED: &[u8] = b"randomness_request"; // Under the oracle program
 
 // Fixed variables
 pub const ACC_SCALE: u128 = 1_000_000_000_000; // 1e12
+pub const BPS_DENOMINATOR: u64 = 10_000;
 
 // Security constants
 pub const MIN_RANDOMNESS_DELAY_SLOTS: u64 = 2;
 pub const MAX_HORSES_PER_PLAYER: u8 = 128;
 pub const MAX_RACING_HORSES_PER_PLAYER: u8 = 25;
 pub const CANCEL_TIMEOUT_SLOTS: u64 = 24; // Approx. 60 seconds
+pub const MAX_LINEUP_SEARCH_NODES: u32 = 4_096; // Compute bound for `optimize_lineup`
+
+// === Horse NFTs (Token-2022 with metadata extension) ===
+pub const HORSE_NFT_NAME_PREFIX: &str = "TENMA Horse #";
+pub const HORSE_NFT_SYMBOL: &str = "TENMAH";
+
+// === Race events ===
+pub const MAX_RACE_ENTRANTS: u8 = 16;
+pub const RACE_PODIUM_SIZE: usize = 3;
+pub const RACE_PURSE_SPLIT_PCT: [u64; RACE_PODIUM_SIZE] = [50, 30, 20]; // 1st, 2nd, 3rd
+pub const RACE_STAMINA_BASE: u64 = 100; // Each stamina point adds 1% to a horse's race weight
 
 // Horse Breeds/Tiers (rarity equivalent)
 pub const GRADE_E: u8 = 0;      // Common - Draft horses, ponies
@@ -19,6 +47,7 @@ pub const GRADE_D: u8 = 1;      // Uncommon - Quarter horses
 pub const GRADE_C: u8 = 2;      // Rare - Standardbreds
 pub const GRADE_B: u8 = 3;      // Double Rare - Hanoverians, Arabians
 pub const GRADE_SS: u8 = 4;     // Legendary - Thoroughbreds, Champions
+pub const HORSE_GRADE_COUNT: usize = 5;
 
 // Initial starter horse IDs
 pub const STARTER_HORSE_IDS: [u16; 3] = [179, 175, 147]; // Starter horses
@@ -39,6 +68,9 @@ pub const STABLE_CONFIGS: [(u8, u64, u64); 11] = [
     (25, 2000, 256_000_000_000),    // Level 10 - Legendary ranch
 ];
 
+// Upper bound on levels in an on-chain `StableTierConfig` (level 0 included)
+pub const MAX_STABLE_TIERS: usize = 16;
+
 // === Horse data ===
 // format: (id, grade, speed, stamina_cost)
 // Speed = racing performance (like hashpower)
@@ -261,6 +293,52 @@ pub const STAGE_1_REWARD_RATE: u64 = 1_180_556;
 pub const STAGE_2_REWARD_RATE: u64 = 918_208;
 pub const STAGE_3_REWARD_RATE: u64 = 655_847;
 
+// === Configurable emission schedule ===
+pub const EMISSION_MODE_SEGMENTS: u8 = 0; // Consecutive (duration_slots, rate) segments
+pub const EMISSION_MODE_HALVING: u8 = 1; // Initial rate halved every interval
+pub const MAX_EMISSION_SEGMENTS: usize = 16;
+
+// === Governance ===
+pub const PARAMETER_TIMELOCK_SLOTS: u64 = SLOTS_PER_DAY; // Delay before a queued parameter change applies
+
+// === Speed history ===
+pub const SPEED_HISTORY_CAPACITY: usize = 128; // Checkpoints kept before the oldest is overwritten
+
+// === Horse catalog ===
+pub const MAX_CATALOG_HORSES: usize = 256;
+pub const CATALOG_NAME_LEN: usize = 24;
+pub const CATALOG_FLAG_SEASONAL: u8 = 1 << 0; // Drops only while `HorseCatalog::season_open`
+pub const CATALOG_FLAG_LIMITED_EDITION: u8 = 1 << 1; // Drops until `CatalogHorse::supply_left` runs out
+
+// === Horse packs ===
+pub const DEFAULT_PACK_TYPE_ID: u16 = 0; // Built-in pack priced by `horse_pack_cost_microtokens`
+pub const DEFAULT_PACK_HORSE_COUNT: u8 = 5;
+pub const DEFAULT_PACK_GRADE_WEIGHTS: [u16; HORSE_GRADE_COUNT] = [500, 250, 150, 90, 10]; // E, D, C, B, SS
+pub const MAX_PACK_HORSES: u8 = 8; // Keeps a pack settle within its compute budget
+
+// === Fusion ===
+pub const FUSION_MIN_INPUTS: usize = 2;
+pub const FUSION_MAX_INPUTS: usize = 5;
+// Upgrade chance each same-grade input adds, indexed by input grade; 0 disables the grade
+pub const FUSION_CHANCE_PER_INPUT_BPS: [u16; HORSE_GRADE_COUNT] = [2_000, 2_000, 2_000, 2_000, 0];
+pub const FUSION_MAX_CHANCE_BPS: u32 = 9_000; // Fusion always risks its inputs
+// Per-input upgrade chance of fusions committed before fusion recipes existed
+pub const LEGACY_FUSION_CHANCE_BPS: u32 = 2_000;
+
+// === Randomness sources ===
+pub const RANDOMNESS_SOURCE_SLOT_HASHES: u8 = 0;
+pub const RANDOMNESS_SOURCE_ORACLE: u8 = 1;
+pub const MAX_RANDOMNESS_ORACLES: usize = 16; // Oracle programs ever configured
+// Oracle request account: 8-byte discriminator, fulfilled slot (0 while pending), value
+pub const ORACLE_FULFILLED_SLOT_OFFSET: usize = 8;
+pub const ORACLE_VALUE_OFFSET: usize = 16;
+pub const ORACLE_REQUEST_LEN: usize = 48;
+
+// === Pair breeding ===
+pub const PAIR_BREEDING_VARIATION_BPS: u32 = 1_000; // Foal stats vary ±10% around the parents' average
+pub const FOAL_ID_START: u16 = 0x8000; // Pair-bred foals get ids from here up; catalog ids stay below
+pub const FOAL_ID_CAPACITY: u16 = u16::MAX - FOAL_ID_START + 1; // Foal ids that fit above FOAL_ID_START
+
 // Helper function to get horse data by ID
 pub fn get_horse_by_id(id: u16) -> Option<(u8, u16, u8)> {
     HORSE_DATA
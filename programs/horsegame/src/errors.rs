@@ -106,5 +106,187 @@ pub enum HorseGameError {
 
     #[msg("Invalid token mint - does not match configured token")]
     InvalidTokenMint,
-}
 
+    #[msg("Invalid stake amount")]
+    InvalidStakeAmount,
+
+    #[msg("Insufficient staked tokens")]
+    InsufficientStakedTokens,
+
+    #[msg("Staking lockup not expired")]
+    StakingLockupNotExpired,
+
+    #[msg("Invalid SOL rewards share")]
+    InvalidSolRewardsShare,
+
+    #[msg("No tokens staked")]
+    NoTokensStaked,
+
+    #[msg("Invalid deposit amount")]
+    InvalidDepositAmount,
+
+    #[msg("Invalid gamble amount")]
+    InvalidGambleAmount,
+
+    #[msg("Invalid gamble odds")]
+    InvalidGambleOdds,
+
+    #[msg("Gamble already pending")]
+    GambleAlreadyPending,
+
+    #[msg("No gamble pending")]
+    NoGamblePending,
+
+    #[msg("Gamble can still be settled")]
+    GambleStillSettleable,
+
+    #[msg("Gamble bankroll account missing")]
+    GambleBankrollMissing,
+
+    #[msg("Invalid race configuration")]
+    InvalidRaceConfig,
+
+    #[msg("Race entry window is closed")]
+    RaceEntryClosed,
+
+    #[msg("Race entry window is still open")]
+    RaceEntryStillOpen,
+
+    #[msg("Race is full")]
+    RaceFull,
+
+    #[msg("Already registered for this race")]
+    AlreadyRegisteredForRace,
+
+    #[msg("Race already settled")]
+    RaceAlreadySettled,
+
+    #[msg("Race not settled")]
+    RaceNotSettled,
+
+    #[msg("No race prize to claim")]
+    NoRacePrize,
+
+    #[msg("Invalid horse NFT")]
+    InvalidHorseNft,
+
+    #[msg("Invalid listing price")]
+    InvalidListingPrice,
+
+    #[msg("Invalid marketplace fee")]
+    InvalidMarketplaceFee,
+
+    #[msg("Cannot buy your own listing")]
+    CannotBuyOwnListing,
+
+    #[msg("Self transfer not allowed")]
+    SelfTransferNotAllowed,
+
+    #[msg("Invalid batch size")]
+    InvalidBatchSize,
+
+    #[msg("Duplicate horse indices")]
+    DuplicateHorseIndices,
+
+    #[msg("Invalid emission schedule")]
+    InvalidEmissionSchedule,
+
+    #[msg("Emission schedule account required")]
+    EmissionScheduleMissing,
+
+    #[msg("Speed history account required")]
+    SpeedHistoryMissing,
+
+    #[msg("Invalid authority")]
+    InvalidAuthority,
+
+    #[msg("Parameter change timelock has not expired")]
+    ParameterChangeNotReady,
+
+    #[msg("Invalid fees wallet")]
+    InvalidFeesWallet,
+
+    #[msg("Invalid stable tiers")]
+    InvalidStableTiers,
+
+    #[msg("Stable tier config account required")]
+    StableTiersMissing,
+
+    #[msg("Invalid catalog horse")]
+    InvalidCatalogHorse,
+
+    #[msg("Horse catalog is full")]
+    HorseCatalogFull,
+
+    #[msg("Horse catalog account required")]
+    HorseCatalogMissing,
+
+    #[msg("Horse not in catalog")]
+    CatalogHorseNotFound,
+
+    #[msg("Invalid pack type")]
+    InvalidPackType,
+
+    #[msg("Pack type account required")]
+    PackTypeMissing,
+
+    #[msg("Pack type is not on sale")]
+    PackTypeInactive,
+
+    #[msg("Pack type sold out")]
+    PackSoldOut,
+
+    #[msg("SOL payment accounts required")]
+    SolPaymentAccountsMissing,
+
+    #[msg("Invalid pity thresholds")]
+    InvalidPityThresholds,
+
+    #[msg("Invalid breeding config")]
+    InvalidBreedingConfig,
+
+    #[msg("Pair breeding is disabled for this grade")]
+    PairBreedingDisabled,
+
+    #[msg("Fusion inputs must all share one grade")]
+    FusionGradeMismatch,
+
+    #[msg("Fusion is disabled for this grade")]
+    FusionDisabled,
+
+    #[msg("Revealed secret does not match the commitment")]
+    InvalidEntropySecret,
+
+    #[msg("Invalid randomness source")]
+    InvalidRandomnessSource,
+
+    #[msg("Randomness oracle account required")]
+    RandomnessOracleMissing,
+
+    #[msg("Invalid randomness oracle account")]
+    InvalidRandomnessOracle,
+
+    #[msg("Race was cancelled")]
+    RaceCancelled,
+
+    #[msg("Race can still be settled")]
+    RaceStillSettleable,
+
+    #[msg("Recipient has a pending action")]
+    RecipientActionPending,
+
+    #[msg("No foal ids left")]
+    FoalIdsExhausted,
+
+    #[msg("Too many randomness oracles")]
+    RandomnessOraclesFull,
+
+    #[msg("Horses can't leave the stable until race entries close")]
+    HorsesLockedForRace,
+
+    #[msg("Race purse exceeds the remaining emission supply")]
+    RacePurseUnavailable,
+
+    #[msg("Stable bred a pair too recently")]
+    PairBreedingCooldown,
+}
@@ -1,5 +1,115 @@
-use crate::{constants::*, errors::HorseGameError};
+use crate::{constants::*, errors::HorseGameError, rng::RandomStream, state::{EmissionSegment, Horse, PackTypeConfig, StableTier}};
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::sysvar::slot_hashes;
+
+/// Result of looking up a slot in the SlotHashes sysvar
+pub enum SlotHashLookup {
+    /// The slot produced a block and its hash is still available
+    Found([u8; 32]),
+    /// The slot is inside the sysvar window but has no entry (skipped by the leader)
+    Skipped,
+    /// The slot is older than every entry in the sysvar
+    Expired,
+}
+
+/// Looks up the hash recorded for `slot` in the SlotHashes sysvar
+pub fn lookup_slot_hash(sysvar_slot_history: &AccountInfo, slot: u64) -> Result<SlotHashLookup> {
+    require!(
+        sysvar_slot_history.key == &slot_hashes::id(),
+        HorseGameError::InvalidSlotHashes
+    );
+
+    let data = sysvar_slot_history.try_borrow_data()?;
+    let num_slot_hashes = u64::from_le_bytes(data[0..8].try_into().unwrap());
+    let mut pos = 8;
+    let mut oldest_slot = u64::MAX;
+    for _ in 0..num_slot_hashes {
+        let entry_slot = u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap());
+        pos += 8;
+        if entry_slot == slot {
+            let mut hash = [0u8; 32];
+            hash.copy_from_slice(&data[pos..pos + 32]);
+            return Ok(SlotHashLookup::Found(hash));
+        }
+        oldest_slot = entry_slot;
+        pos += 32;
+    }
+
+    if slot < oldest_slot {
+        Ok(SlotHashLookup::Expired)
+    } else {
+        Ok(SlotHashLookup::Skipped)
+    }
+}
+
+/// Stake returned when a timed-out gamble is cancelled, given its reveal slot.
+/// A gamble whose hash is still in the sysvar must be settled instead. A
+/// skipped reveal slot never produced an outcome, so the stake is refunded
+/// (up to the bankroll). Once the hash has expired the outcome was public, and
+/// refunding it would let a loser wait out the sysvar for their stake back, so
+/// the stake is forfeited to the bankroll
+pub fn gamble_cancel_refund(
+    reveal: SlotHashLookup,
+    amount: u64,
+    bankroll_balance: u64,
+) -> Result<u64> {
+    match reveal {
+        SlotHashLookup::Found(_) => err!(HorseGameError::GambleStillSettleable),
+        SlotHashLookup::Skipped => Ok(amount.min(bankroll_balance)),
+        SlotHashLookup::Expired => Ok(0),
+    }
+}
+
+/// Result of looking up the first block produced at or after a slot
+pub enum FirstSlotHashLookup {
+    /// The earliest slot at or after the requested one that produced a block
+    Found { slot: u64, hash: [u8; 32] },
+    /// No block at or after the requested slot has been recorded yet
+    Pending,
+    /// The sysvar no longer reaches back to the requested slot, so its first
+    /// block can't be proven
+    Expired,
+}
+
+/// Looks up the first SlotHashes entry at or after `slot`. A skipped slot
+/// re-anchors to the next block, which is just as unknowable at commit time and
+/// leaves the caller no choice of hash
+pub fn lookup_first_slot_hash(
+    sysvar_slot_history: &AccountInfo,
+    slot: u64,
+) -> Result<FirstSlotHashLookup> {
+    require!(
+        sysvar_slot_history.key == &slot_hashes::id(),
+        HorseGameError::InvalidSlotHashes
+    );
+
+    let data = sysvar_slot_history.try_borrow_data()?;
+    let num_slot_hashes = u64::from_le_bytes(data[0..8].try_into().unwrap());
+    let mut pos = 8;
+    let mut first = None;
+    // Entries are newest first, so the last one at or after `slot` is the earliest
+    for _ in 0..num_slot_hashes {
+        let entry_slot = u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap());
+        if entry_slot < slot {
+            return Ok(first.map_or(FirstSlotHashLookup::Pending, |(slot, hash)| {
+                FirstSlotHashLookup::Found { slot, hash }
+            }));
+        }
+        let mut hash = [0u8; 32];
+        hash.copy_from_slice(&data[pos + 8..pos + 40]);
+        if entry_slot == slot {
+            return Ok(FirstSlotHashLookup::Found { slot, hash });
+        }
+        first = Some((entry_slot, hash));
+        pos += 40;
+    }
+
+    if first.is_some() {
+        Ok(FirstSlotHashLookup::Expired)
+    } else {
+        Ok(FirstSlotHashLookup::Pending)
+    }
+}
 
 /// Validates that a horse index is within bounds
 pub fn validate_horse_index(horse_index: u8, horses_len: usize) -> Result<()> {
@@ -10,6 +120,31 @@ pub fn validate_horse_index(horse_index: u8, horses_len: usize) -> Result<()> {
     Ok(())
 }
 
+/// Validates a batch of horse indices: non-empty, bounded, in range and distinct
+pub fn validate_horse_index_batch(
+    horse_indices: &[u8],
+    max_len: usize,
+    horses_len: usize,
+) -> Result<()> {
+    require!(
+        !horse_indices.is_empty() && horse_indices.len() <= max_len,
+        HorseGameError::InvalidBatchSize
+    );
+
+    let mut sorted_indices = horse_indices.to_vec();
+    sorted_indices.sort();
+    for i in 1..sorted_indices.len() {
+        require!(
+            sorted_indices[i] != sorted_indices[i - 1],
+            HorseGameError::DuplicateHorseIndices
+        );
+    }
+    for &index in horse_indices {
+        validate_horse_index(index, horses_len)?;
+    }
+    Ok(())
+}
+
 /// Safely adds feed consumption, checking for overflow
 pub fn safe_add_feed(current: u64, to_add: u64) -> Result<u64> {
     current
@@ -38,37 +173,395 @@ pub fn safe_sub_speed(current: u64, to_sub: u64) -> Result<u64> {
         .ok_or(HorseGameError::ArithmeticOverflow.into())
 }
 
-/// Gets the next higher grade for breeding
+/// Horses that share a (speed, stamina_cost) profile are interchangeable for lineup purposes
+struct LineupGroup {
+    speed: u64,
+    stamina: u64,
+    indices: Vec<u8>,
+}
+
+/// Upper bound on the speed still reachable from groups `from..`: the tighter of
+/// "best horses for the free slots" and "best speed per feed for the free feed"
+fn lineup_speed_bound(
+    groups: &[LineupGroup],
+    ratio_order: &[usize],
+    from: usize,
+    slots_left: u64,
+    feed_left: u64,
+) -> u64 {
+    let mut by_slots = 0u64;
+    let mut slots = slots_left;
+    for group in &groups[from..] {
+        if slots == 0 {
+            break;
+        }
+        let take = slots.min(group.indices.len() as u64);
+        by_slots += take * group.speed;
+        slots -= take;
+    }
+
+    let mut by_feed = 0u64;
+    let mut feed = feed_left;
+    for &g in ratio_order.iter().filter(|&&g| g >= from) {
+        if feed == 0 {
+            break;
+        }
+        let group = &groups[g];
+        let group_feed = group.stamina * group.indices.len() as u64;
+        if group_feed <= feed {
+            by_feed += group.speed * group.indices.len() as u64;
+            feed -= group_feed;
+        } else {
+            by_feed += (group.speed * feed).div_ceil(group.stamina);
+            feed = 0;
+        }
+    }
+
+    by_slots.min(by_feed)
+}
+
+/// Fills slots and feed group by group in `order`, returning how many horses of
+/// each group were taken and their total speed
+fn greedy_lineup_counts(
+    groups: &[LineupGroup],
+    order: impl Iterator<Item = usize>,
+    racing_slots: u64,
+    feed_capacity: u64,
+) -> (Vec<u64>, u64) {
+    let mut counts = vec![0u64; groups.len()];
+    let (mut slots, mut feed, mut speed) = (racing_slots, feed_capacity, 0u64);
+    for g in order {
+        let group = &groups[g];
+        let take = (group.indices.len() as u64)
+            .min(slots)
+            .min(feed / group.stamina.max(1));
+        counts[g] = take;
+        slots -= take;
+        feed -= take * group.stamina;
+        speed += take * group.speed;
+    }
+    (counts, speed)
+}
+
+/// Picks the racing lineup with the highest total speed that fits in
+/// `racing_slots` and `feed_capacity`.
+/// Branch-and-bound over how many horses of each (speed, stamina) group to take,
+/// starting from the better of the fastest-first and speed-per-feed greedy
+/// lineups; the search stops after `MAX_LINEUP_SEARCH_NODES` and keeps the best
+/// lineup found, so a truncated search is never worse than either greedy pick.
+pub fn optimize_racing_lineup(horses: &[Horse], racing_slots: u8, feed_capacity: u64) -> Vec<u8> {
+    let mut groups: Vec<LineupGroup> = Vec::new();
+    for (index, horse) in horses.iter().enumerate() {
+        let (speed, stamina) = (horse.speed as u64, horse.stamina_cost as u64);
+        if speed == 0 || stamina > feed_capacity {
+            continue;
+        }
+        match groups
+            .iter_mut()
+            .find(|group| group.speed == speed && group.stamina == stamina)
+        {
+            Some(group) => group.indices.push(index as u8),
+            None => groups.push(LineupGroup {
+                speed,
+                stamina,
+                indices: vec![index as u8],
+            }),
+        }
+    }
+    groups.sort_by(|a, b| b.speed.cmp(&a.speed).then(a.stamina.cmp(&b.stamina)));
+
+    let mut ratio_order: Vec<usize> = (0..groups.len()).collect();
+    // speed_a / stamina_a > speed_b / stamina_b, without division
+    ratio_order.sort_by(|&a, &b| {
+        let lhs = groups[a].speed as u128 * groups[b].stamina.max(1) as u128;
+        let rhs = groups[b].speed as u128 * groups[a].stamina.max(1) as u128;
+        rhs.cmp(&lhs)
+    });
+
+    let n = groups.len();
+    let mut counts = vec![0u64; n];
+    let (mut best_counts, mut best_speed) =
+        greedy_lineup_counts(&groups, 0..n, racing_slots as u64, feed_capacity);
+    let (ratio_counts, ratio_speed) = greedy_lineup_counts(
+        &groups,
+        ratio_order.iter().copied(),
+        racing_slots as u64,
+        feed_capacity,
+    );
+    if ratio_speed > best_speed {
+        best_counts = ratio_counts;
+        best_speed = ratio_speed;
+    }
+
+    // Explicit stack instead of recursion: depth d holds the state before group d is decided
+    let mut slots_left = vec![0u64; n + 1];
+    let mut feed_left = vec![0u64; n + 1];
+    let mut speed = vec![0u64; n + 1];
+    let mut next_count: Vec<Option<u64>> = vec![None; n + 1];
+    slots_left[0] = racing_slots as u64;
+    feed_left[0] = feed_capacity;
+
+    let mut depth = 0usize;
+    let mut entering = true;
+    let mut nodes = 0u32;
+
+    loop {
+        if entering {
+            entering = false;
+            nodes += 1;
+
+            if speed[depth] > best_speed {
+                best_speed = speed[depth];
+                best_counts[..depth].copy_from_slice(&counts[..depth]);
+                best_counts[depth..].iter_mut().for_each(|c| *c = 0);
+            }
+
+            let prune = depth == n
+                || nodes >= MAX_LINEUP_SEARCH_NODES
+                || speed[depth]
+                    + lineup_speed_bound(
+                        &groups,
+                        &ratio_order,
+                        depth,
+                        slots_left[depth],
+                        feed_left[depth],
+                    )
+                    <= best_speed;
+
+            next_count[depth] = if prune {
+                None
+            } else {
+                let group = &groups[depth];
+                Some(
+                    (group.indices.len() as u64)
+                        .min(slots_left[depth])
+                        .min(feed_left[depth] / group.stamina.max(1)),
+                )
+            };
+        }
+
+        match next_count[depth] {
+            Some(count) => {
+                next_count[depth] = count.checked_sub(1);
+                counts[depth] = count;
+                let group = &groups[depth];
+                slots_left[depth + 1] = slots_left[depth] - count;
+                feed_left[depth + 1] = feed_left[depth] - count * group.stamina;
+                speed[depth + 1] = speed[depth] + count * group.speed;
+                depth += 1;
+                entering = true;
+            }
+            None => {
+                if depth == 0 {
+                    break;
+                }
+                depth -= 1;
+            }
+        }
+    }
+
+    let mut lineup = Vec::new();
+    for (group, &count) in groups.iter().zip(best_counts.iter()) {
+        lineup.extend_from_slice(&group.indices[..count as usize]);
+    }
+    lineup.sort();
+    lineup
+}
+
+/// Winning payout for a gamble: fair odds for `win_chance_bps`, minus the house edge
+pub fn calculate_gamble_payout(amount: u64, win_chance_bps: u16, house_edge_bps: u16) -> u64 {
+    if win_chance_bps == 0 {
+        return 0;
+    }
+    let payout = (amount as u128)
+        .saturating_mul(BPS_DENOMINATOR.saturating_sub(house_edge_bps as u64) as u128)
+        / win_chance_bps as u128;
+    payout.min(u64::MAX as u128) as u64
+}
+
+/// Validates gamble odds: a winning payout never returns less than the stake
+pub fn validate_gamble_odds(win_chance_bps: u64, house_edge_bps: u64) -> Result<()> {
+    require!(
+        win_chance_bps > 0 && win_chance_bps.saturating_add(house_edge_bps) <= BPS_DENOMINATOR,
+        HorseGameError::InvalidGambleOdds
+    );
+    Ok(())
+}
+
+/// Race weight of a horse: speed, boosted by stamina
+pub fn race_weight(speed: u16, stamina_cost: u8) -> u64 {
+    (speed as u64) * (RACE_STAMINA_BASE + stamina_cost as u64) / RACE_STAMINA_BASE
+}
+
+/// Share of `purse` paid to finishing `place`
+pub fn race_prize(purse: u64, place: usize) -> u64 {
+    purse.saturating_mul(RACE_PURSE_SPLIT_PCT[place]) / 100
+}
+
+/// Draws up to `RACE_PODIUM_SIZE` finishers without replacement, each pick
+/// weighted by `weights` and driven by its own 8-byte window of `random_value`
+pub fn draw_race_podium(weights: &[u64], random_value: &[u8; 32]) -> Vec<u8> {
+    let mut remaining: Vec<u64> = weights.iter().map(|w| (*w).max(1)).collect();
+    let mut podium = Vec::with_capacity(RACE_PODIUM_SIZE);
+
+    for place in 0..RACE_PODIUM_SIZE.min(weights.len()) {
+        let total: u64 = remaining.iter().sum();
+        if total == 0 {
+            break;
+        }
+
+        let mut random_bytes: [u8; 8] = [0; 8];
+        random_bytes.copy_from_slice(&random_value[place * 8..place * 8 + 8]);
+        let mut roll = u64::from_le_bytes(random_bytes) % total;
+
+        for (index, weight) in remaining.iter_mut().enumerate() {
+            if roll < *weight {
+                podium.push(index as u8);
+                *weight = 0;
+                break;
+            }
+            roll -= *weight;
+        }
+    }
+
+    podium
+}
+
+/// Display name of a grade, as written into horse NFT metadata
+pub fn grade_name(grade: u8) -> &'static str {
+    match grade {
+        GRADE_E => "E",
+        GRADE_D => "D",
+        GRADE_C => "C",
+        GRADE_B => "B",
+        GRADE_SS => "SS",
+        _ => "?",
+    }
+}
+
+/// Maps a random u32 onto a grade in proportion to `grade_weights`
+pub fn roll_grade(random_u32: u32, grade_weights: &[u16; HORSE_GRADE_COUNT]) -> u8 {
+    let total_weight: u64 = grade_weights.iter().map(|&weight| weight as u64).sum();
+    let mut roll = random_u32 as u64 * total_weight / (u32::MAX as u64 + 1);
+
+    for (grade, &weight) in grade_weights.iter().enumerate() {
+        if roll < weight as u64 {
+            return grade as u8;
+        }
+        roll -= weight as u64;
+    }
+    GRADE_SS
+}
+
+/// Upgrades a pack roll once the player has gone `rolls_without_ss` rolls
+/// without an SS: to at least B from `b_threshold`, to SS at `ss_threshold`
+pub fn apply_pity(grade: u8, rolls_without_ss: u16, b_threshold: u16, ss_threshold: u16) -> u8 {
+    let roll_number = rolls_without_ss as u32 + 1;
+    if ss_threshold > 0 && roll_number >= ss_threshold as u32 {
+        return GRADE_SS;
+    }
+    if b_threshold > 0 && roll_number >= b_threshold as u32 && grade < GRADE_B {
+        return GRADE_B;
+    }
+    grade
+}
+
+/// Checks a pack type before it is stored; `opened` is how many have sold so far
+pub fn validate_pack_type_config(config: &PackTypeConfig, opened: u64) -> Result<()> {
+    require!(
+        config.horse_count > 0 && config.horse_count <= MAX_PACK_HORSES,
+        HorseGameError::InvalidPackType
+    );
+    require!(
+        config.grade_weights.iter().any(|&weight| weight > 0),
+        HorseGameError::InvalidPackType
+    );
+    if let Some(cap) = config.supply_cap {
+        require!(cap >= opened, HorseGameError::InvalidPackType);
+    }
+    Ok(())
+}
+
+/// Scales `base` by a factor drawn uniformly from ±PAIR_BREEDING_VARIATION_BPS
+fn vary_stat(base: u32, rng: &mut RandomStream) -> u32 {
+    let span = 2 * PAIR_BREEDING_VARIATION_BPS + 1;
+    let factor_bps = 10_000 - PAIR_BREEDING_VARIATION_BPS + rng.below(span as u64) as u32;
+    (base * factor_bps + 5_000) / 10_000
+}
+
+/// Foal of a sire/dam pair with id `foal_id`: it takes the lower parent grade
+/// and that parent's look (a coin flip when both share a grade), with speed and
+/// stamina cost varied around the parents' average but kept within the
+/// parents' range, so stats cannot compound across generations.
+/// Returns the foal and the id of the parent whose look it took
+pub fn breed_foal(sire: &Horse, dam: &Horse, foal_id: u16, rng: &mut RandomStream) -> (Horse, u16) {
+    let look = if sire.grade != dam.grade {
+        if sire.grade < dam.grade { sire } else { dam }
+    } else if rng.below(2) == 0 {
+        sire
+    } else {
+        dam
+    };
+
+    let average_speed = (sire.speed as u32 + dam.speed as u32).div_ceil(2);
+    let average_stamina = (sire.stamina_cost as u32 + dam.stamina_cost as u32).div_ceil(2);
+    let speed = vary_stat(average_speed, rng).clamp(
+        sire.speed.min(dam.speed).max(1) as u32,
+        sire.speed.max(dam.speed).max(1) as u32,
+    );
+    let stamina_cost = vary_stat(average_stamina, rng).clamp(
+        sire.stamina_cost.min(dam.stamina_cost).max(1) as u32,
+        sire.stamina_cost.max(dam.stamina_cost).max(1) as u32,
+    );
+
+    let foal = Horse {
+        id: foal_id,
+        grade: look.grade,
+        speed: speed as u16,
+        stamina_cost: stamina_cost as u8,
+    };
+    (foal, look.id)
+}
+
+/// Gets the next higher grade for breeding; SS is the top grade
 pub fn get_next_grade(current_grade: u8) -> Option<u8> {
     match current_grade {
         GRADE_E => Some(GRADE_D),
         GRADE_D => Some(GRADE_C),
         GRADE_C => Some(GRADE_B),
         GRADE_B => Some(GRADE_SS),
-        GRADE_SS => Some(GRADE_SS), // Already at max grade
         _ => None,
     }
 }
 
-/// Calculates the current reward rate based on elapsed time since start_slot
-pub fn calculate_current_reward_rate(current_slot: u64, start_slot: u64) -> u64 {
-    if current_slot < start_slot {
-        return 0;
-    }
+/// Odds that fusing `input_count` horses of `grade` yields one next-grade horse
+pub fn fusion_chance_bps(grade: u8, input_count: usize) -> u32 {
+    let per_input = FUSION_CHANCE_PER_INPUT_BPS
+        .get(grade as usize)
+        .copied()
+        .unwrap_or(0) as u32;
+    (per_input * input_count as u32).min(FUSION_MAX_CHANCE_BPS)
+}
 
-    let elapsed_slots = current_slot.saturating_sub(start_slot);
+/// Built-in emission stages, used until an `EmissionSchedule` is set
+pub const DEFAULT_EMISSION_SEGMENTS: [EmissionSegment; 3] = [
+    EmissionSegment {
+        duration_slots: STAGE_1_DURATION_SLOTS,
+        rate: STAGE_1_REWARD_RATE,
+    },
+    EmissionSegment {
+        duration_slots: STAGE_2_DURATION_SLOTS,
+        rate: STAGE_2_REWARD_RATE,
+    },
+    EmissionSegment {
+        duration_slots: STAGE_3_DURATION_SLOTS,
+        rate: STAGE_3_REWARD_RATE,
+    },
+];
 
-    if elapsed_slots < STAGE_1_DURATION_SLOTS {
-        STAGE_1_REWARD_RATE
-    } else if elapsed_slots < STAGE_1_DURATION_SLOTS + STAGE_2_DURATION_SLOTS {
-        STAGE_2_REWARD_RATE
-    } else if elapsed_slots
-        < STAGE_1_DURATION_SLOTS + STAGE_2_DURATION_SLOTS + STAGE_3_DURATION_SLOTS
-    {
-        STAGE_3_REWARD_RATE
-    } else {
-        0 // After 90 days, no more rewards
-    }
+/// Calculates the current reward rate based on elapsed time since start_slot
+pub fn calculate_current_reward_rate(current_slot: u64, start_slot: u64) -> u64 {
+    segment_rate_at(&DEFAULT_EMISSION_SEGMENTS, start_slot, current_slot) // 0 after 90 days
 }
 
 pub fn calculate_halvings(current_slot: u64, start_slot: u64, halving_interval: u64) -> u64 {
@@ -86,3 +579,461 @@ pub fn reward_after_halvings(initial: u64, halvings: u64) -> u64 {
     initial.checked_shr(halvings as u32).unwrap_or(0)
 }
 
+/// Rate of the segment covering `slot`; segments run back to back from `start_slot`
+pub fn segment_rate_at(segments: &[EmissionSegment], start_slot: u64, slot: u64) -> u64 {
+    if slot < start_slot {
+        return 0;
+    }
+
+    let mut segment_end = start_slot;
+    for segment in segments {
+        segment_end = segment_end.saturating_add(segment.duration_slots);
+        if slot < segment_end {
+            return segment.rate;
+        }
+    }
+    0 // Past the last segment
+}
+
+/// Rewards emitted over `[from, to)`, paying each slot at the rate of its own segment
+pub fn segment_emission(segments: &[EmissionSegment], start_slot: u64, from: u64, to: u64) -> u128 {
+    let from = from.max(start_slot);
+    let mut total = 0u128;
+    let mut segment_start = start_slot;
+
+    for segment in segments {
+        if segment_start >= to {
+            break;
+        }
+        let segment_end = segment_start.saturating_add(segment.duration_slots);
+        let lo = from.max(segment_start);
+        let hi = to.min(segment_end);
+        if hi > lo {
+            total = total.saturating_add((hi - lo) as u128 * segment.rate as u128);
+        }
+        segment_start = segment_end;
+    }
+    total
+}
+
+pub fn halving_rate_at(initial_rate: u64, interval_slots: u64, start_slot: u64, slot: u64) -> u64 {
+    if slot < start_slot || interval_slots == 0 {
+        return 0;
+    }
+    reward_after_halvings(initial_rate, calculate_halvings(slot, start_slot, interval_slots))
+}
+
+/// Rewards emitted over `[from, to)` when the rate halves every `interval_slots`
+pub fn halving_emission(
+    initial_rate: u64,
+    interval_slots: u64,
+    start_slot: u64,
+    from: u64,
+    to: u64,
+) -> u128 {
+    let from = from.max(start_slot);
+    if interval_slots == 0 || to <= from {
+        return 0;
+    }
+
+    let max_halvings = calculate_max_halvings(initial_rate);
+    let mut halvings = calculate_halvings(from, start_slot, interval_slots);
+    let mut cursor = from;
+    let mut total = 0u128;
+
+    while cursor < to && halvings < max_halvings {
+        let epoch_end = start_slot.saturating_add((halvings + 1).saturating_mul(interval_slots));
+        let hi = to.min(epoch_end);
+        let rate = reward_after_halvings(initial_rate, halvings);
+        total = total.saturating_add((hi - cursor) as u128 * rate as u128);
+        cursor = hi;
+        halvings += 1;
+    }
+    total
+}
+
+/// Built-in stable level, as stored on-chain
+pub fn default_stable_tier(level: u8) -> Option<StableTier> {
+    STABLE_CONFIGS
+        .get(level as usize)
+        .map(|&(racing_slots, feed_capacity, cost)| StableTier {
+            racing_slots,
+            feed_capacity,
+            cost,
+        })
+}
+
+/// Checks a stable tier table before it replaces `current`.
+/// Level 0 is the empty pre-purchase stable; each later level must add feed
+/// capacity without losing racing slots. Levels can be added but not removed,
+/// and existing levels cannot shrink, so every player's stable stays valid.
+pub fn validate_stable_tiers(tiers: &[StableTier], current: &[StableTier]) -> Result<()> {
+    require!(
+        tiers.len() >= 2 && tiers.len() <= MAX_STABLE_TIERS && tiers.len() >= current.len(),
+        HorseGameError::InvalidStableTiers
+    );
+    require!(
+        tiers[0].racing_slots == 0 && tiers[0].feed_capacity == 0 && tiers[0].cost == 0,
+        HorseGameError::InvalidStableTiers
+    );
+
+    for pair in tiers.windows(2) {
+        let (lower, upper) = (&pair[0], &pair[1]);
+        require!(
+            upper.racing_slots >= lower.racing_slots
+                && upper.racing_slots <= MAX_RACING_HORSES_PER_PLAYER
+                && upper.feed_capacity > lower.feed_capacity
+                && upper.cost >= lower.cost,
+            HorseGameError::InvalidStableTiers
+        );
+    }
+
+    for (tier, existing) in tiers.iter().zip(current) {
+        require!(
+            tier.racing_slots >= existing.racing_slots
+                && tier.feed_capacity >= existing.feed_capacity,
+            HorseGameError::InvalidStableTiers
+        );
+    }
+
+    Ok(())
+}
+
+/// Checks an emission schedule before it is stored
+pub fn validate_emission_schedule(
+    mode: u8,
+    segments: &[EmissionSegment],
+    halving_initial_rate: u64,
+    halving_interval_slots: u64,
+) -> Result<()> {
+    match mode {
+        EMISSION_MODE_SEGMENTS => {
+            require!(
+                !segments.is_empty() && segments.len() <= MAX_EMISSION_SEGMENTS,
+                HorseGameError::InvalidEmissionSchedule
+            );
+            require!(
+                segments.iter().all(|segment| segment.duration_slots > 0),
+                HorseGameError::InvalidEmissionSchedule
+            );
+        }
+        EMISSION_MODE_HALVING => {
+            require!(
+                halving_initial_rate > 0 && halving_interval_slots > 0,
+                HorseGameError::InvalidEmissionSchedule
+            );
+        }
+        _ => return err!(HorseGameError::InvalidEmissionSchedule),
+    }
+    Ok(())
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Runs `check` against a SlotHashes sysvar holding `slots`, newest first
+    fn with_slot_hashes(slots: &[u64], check: impl FnOnce(&AccountInfo)) {
+        let mut data = (slots.len() as u64).to_le_bytes().to_vec();
+        for &slot in slots {
+            data.extend_from_slice(&slot.to_le_bytes());
+            data.extend_from_slice(&[slot as u8; 32]);
+        }
+        let key = slot_hashes::id();
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let sysvar = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        check(&sysvar);
+    }
+
+    fn first_slot(sysvar: &AccountInfo, slot: u64) -> Option<u64> {
+        match lookup_first_slot_hash(sysvar, slot).unwrap() {
+            FirstSlotHashLookup::Found { slot, hash } => {
+                assert_eq!(hash, [slot as u8; 32]);
+                Some(slot)
+            }
+            _ => None,
+        }
+    }
+
+    /// Small deterministic generator so stables are reproducible without a crate
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn below(&mut self, bound: u64) -> u64 {
+            self.0 = self.0.wrapping_mul(6364136223846793005).wrapping_add(1442695040888963407);
+            (self.0 >> 33) % bound
+        }
+    }
+
+    fn horse(speed: u16, stamina_cost: u8) -> Horse {
+        Horse {
+            id: 1,
+            grade: GRADE_E,
+            speed,
+            stamina_cost,
+        }
+    }
+
+    /// Total speed of `lineup`, asserting it fits the stable
+    fn lineup_speed(horses: &[Horse], lineup: &[u8], racing_slots: u8, feed_capacity: u64) -> u64 {
+        assert!(lineup.len() <= racing_slots as usize);
+        let feed: u64 = lineup.iter().map(|&i| horses[i as usize].stamina_cost as u64).sum();
+        assert!(feed <= feed_capacity);
+        lineup.iter().map(|&i| horses[i as usize].speed as u64).sum()
+    }
+
+    /// Exact optimum by checking every subset
+    fn brute_force_speed(horses: &[Horse], racing_slots: u8, feed_capacity: u64) -> u64 {
+        (0u32..1 << horses.len())
+            .filter(|mask| mask.count_ones() <= racing_slots as u32)
+            .filter_map(|mask| {
+                let chosen = horses.iter().enumerate().filter(|(i, _)| mask & (1 << i) != 0);
+                let feed: u64 = chosen.clone().map(|(_, h)| h.stamina_cost as u64).sum();
+                (feed <= feed_capacity).then(|| chosen.map(|(_, h)| h.speed as u64).sum())
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Exact optimum by dynamic programming over (slots, feed)
+    fn dp_speed(horses: &[Horse], racing_slots: u8, feed_capacity: u64) -> u64 {
+        let (slots, feed) = (racing_slots as usize, feed_capacity as usize);
+        let mut best = vec![vec![0u64; feed + 1]; slots + 1];
+        for horse in horses {
+            let cost = horse.stamina_cost as usize;
+            for s in (1..=slots).rev() {
+                for f in (cost..=feed).rev() {
+                    best[s][f] = best[s][f].max(best[s - 1][f - cost] + horse.speed as u64);
+                }
+            }
+        }
+        best[slots][feed]
+    }
+
+    #[test]
+    fn lineup_matches_brute_force_on_small_stables() {
+        let mut rng = Lcg(1);
+        for _ in 0..500 {
+            let horses: Vec<Horse> = (0..1 + rng.below(12))
+                .map(|_| horse(rng.below(60) as u16, 1 + rng.below(12) as u8))
+                .collect();
+            let racing_slots = 1 + rng.below(6) as u8;
+            let feed_capacity = rng.below(40);
+
+            let lineup = optimize_racing_lineup(&horses, racing_slots, feed_capacity);
+            let expected = brute_force_speed(&horses, racing_slots, feed_capacity);
+            assert_eq!(lineup_speed(&horses, &lineup, racing_slots, feed_capacity), expected);
+            assert_eq!(dp_speed(&horses, racing_slots, feed_capacity), expected);
+        }
+    }
+
+    /// Speed of filling the stable in `key` order, best first
+    fn greedy_speed<K: Ord>(
+        horses: &[Horse],
+        racing_slots: u8,
+        feed_capacity: u64,
+        key: impl Fn(&Horse) -> K,
+    ) -> u64 {
+        let mut sorted = horses.to_vec();
+        sorted.sort_by_key(|horse| std::cmp::Reverse(key(horse)));
+        let (mut slots, mut feed, mut speed) = (racing_slots, feed_capacity, 0u64);
+        for horse in sorted {
+            if slots > 0 && horse.stamina_cost as u64 <= feed {
+                slots -= 1;
+                feed -= horse.stamina_cost as u64;
+                speed += horse.speed as u64;
+            }
+        }
+        speed
+    }
+
+    /// Full stables whose search may be truncated: the lineup is valid, no worse
+    /// than either greedy pick and within 5% of the exact optimum
+    fn check_full_stables(seed: u64, mut random_horse: impl FnMut(&mut Lcg) -> Horse) {
+        let mut rng = Lcg(seed);
+        for _ in 0..50 {
+            let horses: Vec<Horse> = (0..MAX_HORSES_PER_PLAYER)
+                .map(|_| random_horse(&mut rng))
+                .collect();
+            let racing_slots = 1 + rng.below(MAX_RACING_HORSES_PER_PLAYER as u64) as u8;
+            let feed_capacity = 1 + rng.below(2_000);
+
+            let lineup = optimize_racing_lineup(&horses, racing_slots, feed_capacity);
+            let speed = lineup_speed(&horses, &lineup, racing_slots, feed_capacity);
+            let optimum = dp_speed(&horses, racing_slots, feed_capacity);
+            assert!(speed <= optimum);
+            assert!(speed * 100 >= optimum * 95, "{speed} of {optimum}");
+            assert!(speed >= greedy_speed(&horses, racing_slots, feed_capacity, |h| h.speed));
+            assert!(
+                speed
+                    >= greedy_speed(&horses, racing_slots, feed_capacity, |h| {
+                        (h.speed as u64 * 1_000) / h.stamina_cost as u64
+                    })
+            );
+        }
+    }
+
+    #[test]
+    fn lineup_stays_near_the_optimum_on_full_stables() {
+        check_full_stables(2, |rng| {
+            horse(20 + rng.below(400) as u16, 1 + rng.below(40) as u8)
+        });
+    }
+
+    #[test]
+    fn lineup_stays_near_the_optimum_when_speed_tracks_stamina() {
+        // Speed close to 10x stamina leaves the bound almost flat, which is
+        // what exhausts the node budget
+        check_full_stables(3, |rng| {
+            let stamina = 5 + rng.below(30) as u8;
+            horse(stamina as u16 * 10 + rng.below(3) as u16, stamina)
+        });
+    }
+
+    fn foal_stream(i: u64) -> RandomStream {
+        RandomStream::new([i as u8; 32], &Pubkey::new_from_array([(i >> 8) as u8; 32]))
+    }
+
+    fn graded(id: u16, grade: u8, speed: u16, stamina_cost: u8) -> Horse {
+        Horse {
+            id,
+            grade,
+            speed,
+            stamina_cost,
+        }
+    }
+
+    #[test]
+    fn foal_stats_stay_within_the_parents_range() {
+        let sire = graded(1, GRADE_B, 1_000, 20);
+        let dam = graded(2, GRADE_B, 1_100, 30);
+        let mut speeds = Vec::new();
+        for i in 0..500 {
+            let (foal, _) = breed_foal(&sire, &dam, FOAL_ID_START + 7, &mut foal_stream(i));
+            assert_eq!(foal.id, FOAL_ID_START + 7);
+            assert!((1_000..=1_100).contains(&foal.speed), "{}", foal.speed);
+            assert!((20..=30).contains(&foal.stamina_cost), "{}", foal.stamina_cost);
+            speeds.push(foal.speed);
+        }
+        // Varied around the 1_050 average, reaching both parents' values
+        assert!(speeds.contains(&1_000) && speeds.contains(&1_100));
+        assert!(speeds.iter().any(|&speed| speed > 1_000 && speed < 1_100));
+    }
+
+    #[test]
+    fn foal_stats_do_not_compound_across_generations() {
+        let mut sire = graded(1, GRADE_SS, 2_000, 100);
+        let mut dam = graded(2, GRADE_SS, 2_916, 128);
+        for generation in 0..200 {
+            let mut rng = foal_stream(generation);
+            let (foal, _) = breed_foal(&sire, &dam, FOAL_ID_START + generation as u16, &mut rng);
+            assert!((2_000..=2_916).contains(&foal.speed), "{}", foal.speed);
+            assert!((100..=128).contains(&foal.stamina_cost), "{}", foal.stamina_cost);
+            // Keep breeding the faster parent with the newest foal, the best case for drift
+            if sire.speed < dam.speed {
+                sire = foal;
+            } else {
+                dam = foal;
+            }
+        }
+    }
+
+    #[test]
+    fn fusion_odds_scale_with_inputs_but_never_guarantee_an_upgrade() {
+        let odds: Vec<u32> = (FUSION_MIN_INPUTS..=FUSION_MAX_INPUTS)
+            .map(|inputs| fusion_chance_bps(GRADE_E, inputs))
+            .collect();
+        assert!(odds.windows(2).all(|pair| pair[0] < pair[1]), "{odds:?}");
+        for grade in GRADE_E..=GRADE_SS {
+            assert!(fusion_chance_bps(grade, FUSION_MAX_INPUTS) < 10_000);
+        }
+        assert_eq!(fusion_chance_bps(GRADE_SS, FUSION_MAX_INPUTS), 0);
+    }
+
+    #[test]
+    fn foal_takes_the_lower_grade_and_its_look() {
+        let sire = graded(3, GRADE_B, 500, 10);
+        let dam = graded(4, GRADE_D, 100, 4);
+        for i in 0..50 {
+            let (foal, look_id) = breed_foal(&sire, &dam, FOAL_ID_START, &mut foal_stream(i));
+            assert_eq!((foal.grade, look_id), (GRADE_D, 4));
+        }
+
+        // Equal grades take either parent's look
+        let dam = graded(5, GRADE_B, 400, 8);
+        let looks: Vec<u16> = (0..50)
+            .map(|i| breed_foal(&sire, &dam, FOAL_ID_START, &mut foal_stream(i)).1)
+            .collect();
+        assert!(looks.contains(&3) && looks.contains(&5));
+    }
+
+    #[test]
+    fn first_slot_hash_is_the_slot_itself_when_it_produced_a_block() {
+        with_slot_hashes(&[105, 104, 103, 102], |sysvar| {
+            assert_eq!(first_slot(sysvar, 103), Some(103));
+            assert_eq!(first_slot(sysvar, 102), Some(102));
+        });
+    }
+
+    #[test]
+    fn skipped_slot_re_anchors_to_the_next_block() {
+        with_slot_hashes(&[110, 107, 106, 101], |sysvar| {
+            assert_eq!(first_slot(sysvar, 102), Some(106));
+            assert_eq!(first_slot(sysvar, 108), Some(110));
+            assert!(matches!(
+                lookup_slot_hash(sysvar, 102).unwrap(),
+                SlotHashLookup::Skipped
+            ));
+        });
+    }
+
+    #[test]
+    fn first_slot_hash_waits_for_a_block_at_or_after_the_slot() {
+        with_slot_hashes(&[110, 109], |sysvar| {
+            assert!(matches!(
+                lookup_first_slot_hash(sysvar, 111).unwrap(),
+                FirstSlotHashLookup::Pending
+            ));
+        });
+    }
+
+    #[test]
+    fn first_slot_hash_expires_once_the_sysvar_moves_past_the_slot() {
+        // Without an entry before slot 100, a block at 100..=104 may have been evicted
+        with_slot_hashes(&[106, 105], |sysvar| {
+            assert!(matches!(
+                lookup_first_slot_hash(sysvar, 100).unwrap(),
+                FirstSlotHashLookup::Expired
+            ));
+            assert!(matches!(
+                lookup_slot_hash(sysvar, 100).unwrap(),
+                SlotHashLookup::Expired
+            ));
+        });
+    }
+
+    #[test]
+    fn cancelled_gambles_refund_skipped_reveals_and_forfeit_expired_ones() {
+        // Reveal slots: 104 produced a block, 102 was skipped, 100 has expired
+        with_slot_hashes(&[106, 104, 101], |sysvar| {
+            let refund = |slot| gamble_cancel_refund(lookup_slot_hash(sysvar, slot)?, 500, 300);
+            assert_eq!(
+                refund(104).err(),
+                Some(HorseGameError::GambleStillSettleable.into())
+            );
+            assert_eq!(refund(102).unwrap(), 300); // Capped by the bankroll
+            assert_eq!(refund(100).unwrap(), 0);
+        });
+    }
+}
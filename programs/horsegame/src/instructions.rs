@@ -1,8 +1,21 @@
-use crate::{constants::*, errors::HorseGameError, helpers::*, state::*};
+use crate::{
+    constants::*,
+    errors::HorseGameError,
+    helpers::*,
+    randomness::{OracleRandomness, RandomnessSource, Reveal, SlotHashesRandomness},
+    rng::{revealed_secret, RandomStream},
+    state::*,
+};
 use anchor_lang::prelude::*;
-use anchor_lang::solana_program::sysvar::slot_hashes;
 use anchor_spl::{
     associated_token::AssociatedToken,
+    token_2022::{self, spl_token_2022::instruction::AuthorityType, Token2022},
+    token_2022_extensions::{
+        spl_pod::optional_keys::OptionalNonZeroPubkey,
+        spl_token_metadata_interface::state::{Field, TokenMetadata},
+        token_metadata_initialize, token_metadata_update_field, TokenMetadataInitialize,
+        TokenMetadataUpdateField,
+    },
     token_interface::{self, Burn, Mint, TokenAccount, TokenInterface},
 };
 
@@ -30,10 +43,25 @@ pub struct HorseReleased {
     pub horse_index: u8,
 }
 
+/// Horse awarded by a pack and the stable index it landed at
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PackHorse {
+    pub horse_index: u8,
+    pub id: u16,
+    pub grade: u8,
+    pub speed: u16,
+    pub stamina_cost: u8,
+}
+
 #[event]
 pub struct HorsePackOpened {
     pub player: Pubkey,
-    pub horse_ids: [u8; 5],
+    pub pack_type: u16,
+    pub horses: Vec<PackHorse>,
+    pub reveal_slot: u64,
+    pub slot_hash: [u8; 32], // Randomness the rolls were taken from (SlotHashes entry or oracle value)
+    pub secret: [u8; 32],    // Player secret mixed into the rolls
+    pub pity_counter: u16,   // Rolls since the last SS, after this pack
 }
 
 #[event]
@@ -46,7 +74,46 @@ pub struct HorsesBred {
 /// ────────────────────────────────────────────────────────────────────────────
 /// INTERNAL: update the global accumulator
 /// ────────────────────────────────────────────────────────────────────────────
-fn update_pool(gs: &mut GlobalState, slot_now: u64) {
+/// Optional accounts `update_pool` needs once their `GlobalState` flags are set
+struct PoolAccounts<'a, 'info> {
+    emission_schedule: &'a Option<Box<Account<'info, EmissionSchedule>>>,
+    speed_history: &'a mut Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+fn update_pool(gs: &mut GlobalState, pool: PoolAccounts, slot_now: u64) -> Result<()> {
+    let schedule = if gs.emission_schedule_enabled {
+        let schedule = pool
+            .emission_schedule
+            .as_deref()
+            .ok_or(HorseGameError::EmissionScheduleMissing)?;
+        Some(&**schedule)
+    } else {
+        None
+    };
+
+    // Snapshot the speed that earned this window before callers change it
+    let speed_during_window = gs.total_speed;
+    accrue_pool_rewards(gs, schedule, slot_now);
+
+    if gs.speed_history_enabled {
+        let history = pool
+            .speed_history
+            .as_deref_mut()
+            .ok_or(HorseGameError::SpeedHistoryMissing)?;
+        history.record(slot_now, speed_during_window, gs.cumulative_rewards);
+    }
+    Ok(())
+}
+
+/// Emission supply not yet accrued nor held back for race purses
+fn unreserved_supply(gs: &GlobalState) -> u64 {
+    let minted_minus_burn = gs.cumulative_rewards.saturating_sub(gs.burned_tokens);
+    gs.total_supply
+        .saturating_sub(minted_minus_burn)
+        .saturating_sub(gs.race_purses_reserved)
+}
+
+fn accrue_pool_rewards(gs: &mut GlobalState, schedule: Option<&EmissionSchedule>, slot_now: u64) {
     if slot_now < gs.start_slot {
         gs.last_reward_slot = gs.start_slot;
         return;
@@ -57,26 +124,34 @@ fn update_pool(gs: &mut GlobalState, slot_now: u64) {
         return;
     }
 
-    let rate_now = calculate_current_reward_rate(slot_now, gs.start_slot);
+    let (mut reward, rate_now) = match schedule {
+        Some(schedule) => (
+            schedule.emission_between(gs.start_slot, gs.last_reward_slot, slot_now),
+            schedule.rate_at(gs.start_slot, slot_now),
+        ),
+        None => (
+            segment_emission(
+                &DEFAULT_EMISSION_SEGMENTS,
+                gs.start_slot,
+                gs.last_reward_slot,
+                slot_now,
+            ),
+            calculate_current_reward_rate(slot_now, gs.start_slot),
+        ),
+    };
 
-    let minted_minus_burn = gs.cumulative_rewards.saturating_sub(gs.burned_tokens);
-    let remaining_supply = gs.total_supply.saturating_sub(minted_minus_burn);
+    let remaining_supply = unreserved_supply(gs);
 
-    let dust_threshold = if gs.dust_threshold_divisor > 0 {
-        gs.total_supply / gs.dust_threshold_divisor
-    } else {
-        0
-    };
+    let dust_threshold = gs
+        .total_supply
+        .checked_div(gs.dust_threshold_divisor)
+        .unwrap_or(0);
 
-    if remaining_supply <= dust_threshold || rate_now == 0 {
+    if remaining_supply <= dust_threshold || reward == 0 {
         gs.last_reward_slot = slot_now;
         return;
     }
 
-    let slots_elapsed = (slot_now - gs.last_reward_slot) as u128;
-    let mut reward = slots_elapsed
-        .checked_mul(rate_now as u128)
-        .unwrap_or(u128::MAX);
     reward = reward.min(remaining_supply as u128);
 
     gs.acc_tokens_per_speed += reward * ACC_SCALE / gs.total_speed as u128;
@@ -86,20 +161,366 @@ fn update_pool(gs: &mut GlobalState, slot_now: u64) {
     gs.last_reward_slot = slot_now;
 }
 
+/// ────────────────────────────────────────────────────────────────────────────
+/// INTERNAL: update the staking accumulator (rewards per staked token)
+/// ────────────────────────────────────────────────────────────────────────────
+fn update_staking_pool(gs: &mut GlobalState, slot_now: u64) {
+    if slot_now < gs.start_slot {
+        gs.last_staking_reward_slot = gs.start_slot;
+        return;
+    }
+
+    if slot_now <= gs.last_staking_reward_slot || gs.total_staked_tokens == 0 {
+        gs.last_staking_reward_slot = slot_now;
+        return;
+    }
+
+    let remaining_supply = unreserved_supply(gs);
+
+    let dust_threshold = gs
+        .total_supply
+        .checked_div(gs.dust_threshold_divisor)
+        .unwrap_or(0);
+
+    if remaining_supply <= dust_threshold || gs.token_reward_rate == 0 {
+        gs.last_staking_reward_slot = slot_now;
+        return;
+    }
+
+    let slots_elapsed = (slot_now - gs.last_staking_reward_slot) as u128;
+    let mut reward = slots_elapsed.saturating_mul(gs.token_reward_rate as u128);
+    reward = reward.min(remaining_supply as u128);
+
+    gs.acc_token_rewards_per_token += reward * ACC_SCALE / gs.total_staked_tokens as u128;
+    gs.cumulative_rewards = gs.cumulative_rewards.saturating_add(reward as u64);
+
+    gs.last_staking_reward_slot = slot_now;
+}
+
+/// Moves everything the player's stake earned since their last checkpoint
+/// into `unpaid_staking_rewards` and returns the total now owed to them
+fn accrue_staking_rewards(player: &mut Player, gs: &GlobalState) -> u64 {
+    let accrued = (player.staked_tokens as u128).saturating_mul(
+        gs.acc_token_rewards_per_token
+            .saturating_sub(player.last_acc_token_rewards_per_token),
+    ) / ACC_SCALE;
+    player.last_acc_token_rewards_per_token = gs.acc_token_rewards_per_token;
+    player.unpaid_staking_rewards = player
+        .unpaid_staking_rewards
+        .saturating_add(accrued.min(u64::MAX as u128) as u64);
+    player.unpaid_staking_rewards
+}
+
+/// Helper to settle and transfer staking rewards for a player
+/// Must run before `staked_tokens` changes so the old balance earns up to now.
+/// Staking and racing rewards (including race purses) are paid from the same
+/// `rewards_vault`, so a payout is capped at its balance and the remainder
+/// stays owed in `unpaid_staking_rewards` until the vault is topped up
+fn settle_staking_rewards<'info>(
+    player: &mut Box<Account<'info, Player>>,
+    gs: &mut Account<'info, GlobalState>,
+    player_token_account: &AccountInfo<'info>,
+    token_mint: &InterfaceAccount<'info, Mint>,
+    rewards_vault: &InterfaceAccount<'info, TokenAccount>,
+    token_program: &AccountInfo<'info>,
+    global_state_bump: u8,
+) -> Result<u64> {
+    let now = Clock::get()?.slot;
+    update_staking_pool(gs, now);
+
+    let owed = accrue_staking_rewards(player, gs);
+
+    // Cap by actual vault balance to prevent failed transfers
+    let vault_balance = rewards_vault.amount;
+    let pending = owed.min(vault_balance);
+    if pending < owed {
+        msg!("⚠️ Staking rewards capped to vault balance: {}", vault_balance);
+    }
+    player.unpaid_staking_rewards = owed - pending;
+
+    if pending == 0 {
+        return Ok(0);
+    }
+
+    player.claimed_token_rewards = player.claimed_token_rewards.saturating_add(pending);
+
+    let seeds = &[
+        GLOBAL_STATE_SEED,
+        &[global_state_bump],
+    ];
+    let signer = &[&seeds[..]];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            token_program.clone(),
+            token_interface::TransferChecked {
+                from: rewards_vault.to_account_info(),
+                mint: token_mint.to_account_info(),
+                to: player_token_account.clone(),
+                authority: gs.to_account_info(),
+            },
+            signer,
+        ),
+        pending,
+        token_mint.decimals,
+    )?;
+
+    Ok(pending)
+}
+
+/// Creates a program-owned PDA of `space` bytes paid by `payer`. Lamports sent
+/// to the address beforehand would make `create_account` fail, so such an
+/// account is topped up, allocated and assigned instead
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+    let current = account.lamports();
+
+    if current == 0 {
+        return anchor_lang::system_program::create_account(
+            CpiContext::new_with_signer(
+                system_program.clone(),
+                anchor_lang::system_program::CreateAccount {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+                &[signer_seeds],
+            ),
+            rent,
+            space as u64,
+            &crate::ID,
+        );
+    }
+
+    if rent > current {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.clone(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.clone(),
+                    to: account.clone(),
+                },
+            ),
+            rent - current,
+        )?;
+    }
+    anchor_lang::system_program::allocate(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Allocate {
+                account_to_allocate: account.clone(),
+            },
+            &[signer_seeds],
+        ),
+        space as u64,
+    )?;
+    anchor_lang::system_program::assign(
+        CpiContext::new_with_signer(
+            system_program.clone(),
+            anchor_lang::system_program::Assign {
+                account_to_assign: account.clone(),
+            },
+            &[signer_seeds],
+        ),
+        &crate::ID,
+    )
+}
+
+/// Splits a SOL fee into (stakers share, fees wallet share)
+/// Everything goes to the fees wallet while nobody is staked
+fn split_sol_fee(gs: &GlobalState, fee: u64) -> (u64, u64) {
+    if gs.total_staked_tokens == 0 {
+        return (0, fee);
+    }
+    let stakers_share = fee
+        .saturating_mul(gs.sol_rewards_share as u64)
+        .saturating_div(100);
+    (stakers_share, fee.saturating_sub(stakers_share))
+}
+
+/// Helper to move lamports into the SOL rewards wallet and credit stakers
+fn deposit_sol_rewards_to_stakers<'info>(
+    gs: &mut GlobalState,
+    from: &AccountInfo<'info>,
+    sol_rewards_wallet: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    amount: u64,
+) -> Result<()> {
+    require!(gs.total_staked_tokens > 0, HorseGameError::NoTokensStaked);
+
+    anchor_lang::system_program::transfer(
+        CpiContext::new(
+            system_program.clone(),
+            anchor_lang::system_program::Transfer {
+                from: from.clone(),
+                to: sol_rewards_wallet.clone(),
+            },
+        ),
+        amount,
+    )?;
+
+    gs.acc_sol_rewards_per_token += amount as u128 * ACC_SCALE / gs.total_staked_tokens as u128;
+    gs.total_sol_deposited = gs.total_sol_deposited.saturating_add(amount);
+
+    Ok(())
+}
+
+/// Moves all SOL the player's stake earned since their last checkpoint into
+/// `unpaid_sol_rewards` and returns the total now owed to them
+fn accrue_sol_rewards(player: &mut Player, gs: &GlobalState) -> u64 {
+    let accrued = (player.staked_tokens as u128).saturating_mul(
+        gs.acc_sol_rewards_per_token
+            .saturating_sub(player.last_acc_sol_rewards_per_token),
+    ) / ACC_SCALE;
+    player.last_acc_sol_rewards_per_token = gs.acc_sol_rewards_per_token;
+    player.unpaid_sol_rewards = player
+        .unpaid_sol_rewards
+        .saturating_add(accrued.min(u64::MAX as u128) as u64);
+    player.unpaid_sol_rewards
+}
+
+/// Helper to settle and pay SOL rewards for a staker
+/// Must run before `staked_tokens` changes, like `settle_staking_rewards`.
+/// A payout is capped at the wallet balance above its rent reserve and the
+/// remainder stays owed in `unpaid_sol_rewards` for a later claim
+fn settle_sol_rewards<'info>(
+    player: &mut Box<Account<'info, Player>>,
+    gs: &GlobalState,
+    sol_rewards_wallet: &mut Account<'info, SolRewardsWallet>,
+    player_wallet: &AccountInfo<'info>,
+) -> Result<u64> {
+    let owed = accrue_sol_rewards(player, gs);
+
+    // Never dip into the wallet's rent-exempt reserve
+    let wallet_info = sol_rewards_wallet.to_account_info();
+    let rent_reserve = Rent::get()?.minimum_balance(wallet_info.data_len());
+    let available = wallet_info.lamports().saturating_sub(rent_reserve);
+    let pending = owed.min(available);
+    if pending < owed {
+        msg!("⚠️ SOL rewards capped to wallet balance: {}", available);
+    }
+    player.unpaid_sol_rewards = owed - pending;
+
+    if pending == 0 {
+        return Ok(0);
+    }
+
+    **wallet_info.try_borrow_mut_lamports()? -= pending;
+    **player_wallet.try_borrow_mut_lamports()? += pending;
+    sol_rewards_wallet.total_claimed = sol_rewards_wallet.total_claimed.saturating_add(pending);
+
+    Ok(pending)
+}
+
+/// Stable level from the on-chain tier table once enabled, else `STABLE_CONFIGS`
+fn stable_tier<'info>(
+    gs: &GlobalState,
+    stable_tiers: &Option<Box<Account<'info, StableTierConfig>>>,
+    level: u8,
+) -> Result<StableTier> {
+    let tier = if gs.stable_tiers_enabled {
+        stable_tiers
+            .as_deref()
+            .ok_or(HorseGameError::StableTiersMissing)?
+            .tier(level)
+    } else {
+        default_stable_tier(level)
+    };
+    tier.ok_or_else(|| error!(HorseGameError::InvalidStableType))
+}
+
+/// Draws a horse of `grade` from the on-chain catalog once enabled, else from
+/// HORSE_DATA; `pick` maps the size of the grade's pool to an index in it
+fn draw_horse<'info>(
+    gs: &GlobalState,
+    horse_catalog: &mut Option<Box<Account<'info, HorseCatalog>>>,
+    grade: u8,
+    pick: impl FnOnce(usize) -> usize,
+) -> Result<Option<Horse>> {
+    if gs.horse_catalog_enabled {
+        let catalog = horse_catalog
+            .as_deref_mut()
+            .ok_or(HorseGameError::HorseCatalogMissing)?;
+        let pool = catalog.droppable(grade);
+        if pool.is_empty() {
+            return Ok(None);
+        }
+        let picked = pick(pool.len());
+        let horse = pool[picked];
+        catalog.record_drop(grade, picked);
+        return Ok(Some(Horse {
+            id: horse.id,
+            grade,
+            speed: horse.speed,
+            stamina_cost: horse.stamina_cost,
+        }));
+    }
+
+    let horses_of_grade: Vec<&(u16, u8, u16, u8)> = HORSE_DATA
+        .iter()
+        .filter(|(_, horse_grade, _, _)| *horse_grade == grade)
+        .collect();
+    if horses_of_grade.is_empty() {
+        return Ok(None);
+    }
+    let (id, _, speed, stamina_cost) = *horses_of_grade[pick(horses_of_grade.len())];
+    Ok(Some(Horse {
+        id,
+        grade,
+        speed,
+        stamina_cost,
+    }))
+}
+
+/// Reveals the randomness for the player's pending action from the source
+/// and oracle recorded at its commit
+fn reveal_randomness<'info>(
+    player: &Account<'info, Player>,
+    slot_hashes: &AccountInfo<'info>,
+    randomness_oracle: &Option<AccountInfo<'info>>,
+    randomness_oracles: &Option<Box<Account<'info, RandomnessOracles>>>,
+    current_slot: u64,
+) -> Result<Reveal> {
+    match player.pending_randomness_source {
+        RANDOMNESS_SOURCE_SLOT_HASHES => {
+            SlotHashesRandomness { sysvar: slot_hashes }.reveal(player.commit_slot, current_slot)
+        }
+        RANDOMNESS_SOURCE_ORACLE => OracleRandomness {
+            request: randomness_oracle
+                .as_ref()
+                .ok_or(HorseGameError::RandomnessOracleMissing)?,
+            oracle_program: *randomness_oracles
+                .as_ref()
+                .ok_or(HorseGameError::RandomnessOracleMissing)?
+                .programs
+                .get(player.pending_oracle_index as usize)
+                .ok_or(HorseGameError::InvalidRandomnessOracle)?,
+            player: player.key(),
+        }
+        .reveal(player.commit_slot, current_slot),
+        _ => err!(HorseGameError::InvalidRandomnessSource),
+    }
+}
+
 /// Helper to settle and transfer rewards for a player
 /// Rewards are capped by actual vault balance to prevent failed transfers
 fn settle_and_mint_rewards<'info>(
     player: &mut Box<Account<'info, Player>>,
     gs: &mut Account<'info, GlobalState>,
+    pool: PoolAccounts<'_, 'info>,
     now: u64,
     player_token_account: &AccountInfo<'info>,
-    token_mint: &AccountInfo<'info>,
+    token_mint: &InterfaceAccount<'info, Mint>,
     rewards_vault: &InterfaceAccount<'info, TokenAccount>,
     token_program: &AccountInfo<'info>,
     global_state_bump: u8,
-    decimals: u8,
 ) -> Result<u64> {
-    update_pool(gs, now);
+    update_pool(gs, pool, now)?;
 
     if now <= gs.start_slot {
         player.last_claim_slot = now;
@@ -156,14 +577,14 @@ fn settle_and_mint_rewards<'info>(
             token_program.clone(),
             token_interface::TransferChecked {
                 from: rewards_vault.to_account_info(),
-                mint: token_mint.clone(),
+                mint: token_mint.to_account_info(),
                 to: player_token_account.clone(),
                 authority: gs.to_account_info(),
             },
             signer,
         ),
         player_amount,
-        decimals,
+        token_mint.decimals,
     )?;
 
     Ok(pending)
@@ -180,7 +601,7 @@ pub struct InitializeProgram<'info> {
         init,
         payer = authority,
         // Space: 8 (disc) + 32*4 (pubkeys) + 1 (bool) + 8*5 (u64) + 16 (u128) + 8 (u64) + 1*3 (u8s) + 8*13 (u64) + 16*2 (u128) + 8*3 (u64) + 64 (padding) = 436
-        space = 8 + 32 + 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8 + 16 + 8 + 1 + 1 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 16 + 16 + 8 + 8 + 8 + 64,
+        space = 8 + 32 + 32 + 32 + 32 + 1 + 8 + 8 + 8 + 8 + 8 + 16 + 8 + 1 + 1 + 1 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 16 + 16 + 8 + 8 + 8 + 1 + 63,
         seeds=[GLOBAL_STATE_SEED],  // Fixed seed - no mint dependency!
         bump
     )]
@@ -249,6 +670,22 @@ pub fn initialize_program(
     gs.last_staking_reward_slot = start_slot;
     gs.token_reward_rate = token_reward_rate;
     gs.total_sol_deposited = 0;
+    gs.sol_rewards_share = 0;
+
+    gs.gamble_win_chance_bps = 5_000; // 50%
+    gs.gamble_house_edge_bps = 300;   // 3%
+
+    gs.marketplace_fee_bps = 500; // 5%
+    gs.emission_schedule_enabled = false; // Built-in stages until a schedule is set
+    gs.speed_history_enabled = false;
+    gs.stable_tiers_enabled = false; // STABLE_CONFIGS until a tier table is set
+    gs.horse_catalog_enabled = false; // HORSE_DATA until the catalog is created
+    gs.pity_b_threshold = 0;
+    gs.pity_ss_threshold = 0;
+    gs.randomness_source = RANDOMNESS_SOURCE_SLOT_HASHES;
+    gs.randomness_oracle_index = 0;
+    gs.gamble_liability = 0;
+    gs.race_purses_reserved = 0;
 
     emit!(ProgramInitialized {
         authority: gs.authority,
@@ -378,7 +815,7 @@ pub struct PurchaseInitialStable<'info> {
     #[account(
         init,
         payer = player_wallet,
-        space = 8 + 32 + 10 + (MAX_HORSES_PER_PLAYER as usize * 6) + 1 + 16 + 8 + 8 + 33 + 16 + 8 + 8 + 8 + 8 + 8 + 130 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 16 + 16 + 8 + 8 + 8 + 64,
+        space = 8 + 32 + 10 + (MAX_HORSES_PER_PLAYER as usize * 6) + 1 + 16 + 8 + 8 + 33 + 16 + 8 + 8 + 8 + 8 + 8 + 130 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 8 + 16 + 16 + 8 + 8 + 8 + 8 + 56,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -389,6 +826,14 @@ pub struct PurchaseInitialStable<'info> {
         constraint = fees_wallet.key() == global_state.fees_wallet @ HorseGameError::Unauthorized
     )]
     pub fees_wallet: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = player_wallet,
+        space = 8 + 8,
+        seeds = [SOL_REWARDS_WALLET_SEED],
+        bump
+    )]
+    pub sol_rewards_wallet: Box<Account<'info, SolRewardsWallet>>,
     /// CHECK: This is the referrer's wallet
     #[account(mut)]
     pub referrer_wallet: Option<AccountInfo<'info>>,
@@ -402,6 +847,15 @@ pub struct PurchaseInitialStable<'info> {
     pub token_program: Interface<'info, TokenInterface>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub system_program: Program<'info, System>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+    /// Required once `global_state.stable_tiers_enabled` is set
+    #[account(seeds = [STABLE_TIERS_SEED], bump)]
+    pub stable_tiers: Option<Box<Account<'info, StableTierConfig>>>,
 }
 
 #[event]
@@ -434,9 +888,28 @@ pub fn purchase_initial_stable(ctx: Context<PurchaseInitialStable>) -> Result<()
         );
     }
 
-    update_pool(gs, slot);
+    update_pool(
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
+    )?;
+
+    // Transfer SOL fee, routing the stakers' share to the SOL rewards wallet
+    let (stakers_share, fees_share) = split_sol_fee(gs, gs.initial_stable_purchase_fee_lamports);
+
+    if stakers_share > 0 {
+        deposit_sol_rewards_to_stakers(
+            gs,
+            &ctx.accounts.player_wallet.to_account_info(),
+            &ctx.accounts.sol_rewards_wallet.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            stakers_share,
+        )?;
+    }
 
-    // Transfer SOL fee
     anchor_lang::system_program::transfer(
         CpiContext::new(
             ctx.accounts.system_program.to_account_info(),
@@ -445,12 +918,16 @@ pub fn purchase_initial_stable(ctx: Context<PurchaseInitialStable>) -> Result<()
                 to: ctx.accounts.fees_wallet.to_account_info(),
             },
         ),
-        gs.initial_stable_purchase_fee_lamports,
+        fees_share,
     )?;
 
     // Initialize player
     player.owner = ctx.accounts.player_wallet.key();
-    let (racing_slots, feed_capacity, _) = STABLE_CONFIGS[1];
+    let StableTier {
+        racing_slots,
+        feed_capacity,
+        ..
+    } = stable_tier(gs, &ctx.accounts.stable_tiers, 1)?;
     player.stable = Stable {
         stable_type: 1,
         racing_slots,
@@ -486,6 +963,7 @@ pub fn purchase_initial_stable(ctx: Context<PurchaseInitialStable>) -> Result<()
     player.total_gamble_wins = 0;
     player.pending_action = PendingRandomAction::None;
     player.commit_slot = 0;
+    player.entropy_commitment = [0; 32];
 
     player.total_earnings_for_referrer = 0;
     player.total_horse_packs_opened = 0;
@@ -503,7 +981,14 @@ pub fn purchase_initial_stable(ctx: Context<PurchaseInitialStable>) -> Result<()
     player.total_races_entered = 0;
     player.total_race_wins = 0;
 
-    player.padding = [0u8; 64];
+    player.listings_created = 0;
+    player.pity_counter = 0;
+
+    player.unpaid_sol_rewards = 0;
+
+    player.unpaid_staking_rewards = 0;
+
+    player.race_entry_lock_slot = 0;
 
     emit!(InitialStablePurchased {
         player_wallet: ctx.accounts.player_wallet.key(),
@@ -562,6 +1047,12 @@ pub struct ReleaseHorse<'info> {
     #[account()]
     pub token_mint: InterfaceAccount<'info, Mint>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
 }
 
 pub fn release_horse(ctx: Context<ReleaseHorse>, horse_index: u8) -> Result<()> {
@@ -582,15 +1073,19 @@ pub fn release_horse(ctx: Context<ReleaseHorse>, horse_index: u8) -> Result<()>
     settle_and_mint_rewards(
         player,
         gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
         slot,
         &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.token_mint,
         &ctx.accounts.rewards_vault,
         &ctx.accounts.token_program.to_account_info(),
         ctx.bumps.global_state,
-        ctx.accounts.token_mint.decimals,
     )?;
 
+    player.require_horses_unlocked(slot)?;
     player.batch_remove_horses(&[horse_index])?;
 
     emit!(HorseReleased {
@@ -640,6 +1135,12 @@ pub struct EnterRace<'info> {
     )]
     pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
 }
 
 pub fn enter_race(ctx: Context<EnterRace>, horse_index: u8) -> Result<()> {
@@ -650,13 +1151,16 @@ pub fn enter_race(ctx: Context<EnterRace>, horse_index: u8) -> Result<()> {
     settle_and_mint_rewards(
         player,
         gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
         slot,
         &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.token_mint,
         &ctx.accounts.rewards_vault,
         &ctx.accounts.token_program.to_account_info(),
         ctx.bumps.global_state,
-        ctx.accounts.token_mint.decimals,
     )?;
 
     validate_horse_index(horse_index, player.horse_count as usize)?;
@@ -739,6 +1243,12 @@ pub struct WithdrawFromRace<'info> {
     )]
     pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
 }
 
 pub fn withdraw_from_race(ctx: Context<WithdrawFromRace>, horse_index: u8) -> Result<()> {
@@ -749,13 +1259,16 @@ pub fn withdraw_from_race(ctx: Context<WithdrawFromRace>, horse_index: u8) -> Re
     settle_and_mint_rewards(
         player,
         gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
         slot,
         &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.token_mint,
         &ctx.accounts.rewards_vault,
         &ctx.accounts.token_program.to_account_info(),
         ctx.bumps.global_state,
-        ctx.accounts.token_mint.decimals,
     )?;
 
     validate_horse_index(horse_index, player.horse_count as usize)?;
@@ -789,17 +1302,16 @@ pub fn withdraw_from_race(ctx: Context<WithdrawFromRace>, horse_index: u8) -> Re
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-/// UPGRADE STABLE
+/// ENTER RACE BATCH (stake several horses at once)
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-#[instruction(stable_type: u8)]
-pub struct UpgradeStable<'info> {
+pub struct EnterRaceBatch<'info> {
     #[account(mut)]
     pub player_wallet: Signer<'info>,
     #[account(
         mut,
         constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
-        constraint = player.stable.stable_type + 1 == stable_type && (stable_type as usize) <= STABLE_CONFIGS.len() - 1 @ HorseGameError::InvalidStableType,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePendingBreeding,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -818,87 +1330,114 @@ pub struct UpgradeStable<'info> {
         bump,
     )]
     pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
         constraint = player_token_account.mint == global_state.token_mint,
         constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
     )]
     pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
-    #[account(
-        mut,
-        constraint = fees_token_account.mint == global_state.token_mint,
-        constraint = fees_token_account.owner == global_state.fees_wallet @ HorseGameError::Unauthorized
-    )]
-    pub fees_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
-    #[account(mut)]
-    pub token_mint: InterfaceAccount<'info, Mint>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
 }
 
-pub fn upgrade_stable(ctx: Context<UpgradeStable>, stable_type: u8) -> Result<()> {
+#[event]
+pub struct HorsesEnteredRaceBatch {
+    pub player: Pubkey,
+    pub horse_indices: Vec<u8>,
+    pub speed_added: u64,
+    pub feed_added: u64,
+}
+
+pub fn enter_race_batch(ctx: Context<EnterRaceBatch>, horse_indices: Vec<u8>) -> Result<()> {
     let slot = Clock::get()?.slot;
     let player = &mut ctx.accounts.player;
     let gs = &mut ctx.accounts.global_state;
 
-    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
-
     settle_and_mint_rewards(
         player,
         gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
         slot,
         &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.token_mint,
         &ctx.accounts.rewards_vault,
         &ctx.accounts.token_program.to_account_info(),
         ctx.bumps.global_state,
-        ctx.accounts.token_mint.decimals,
     )?;
 
-    let (racing_slots, feed_capacity, cost) = STABLE_CONFIGS[stable_type as usize];
+    validate_horse_index_batch(
+        &horse_indices,
+        MAX_RACING_HORSES_PER_PLAYER as usize,
+        player.horse_count as usize,
+    )?;
 
     require!(
-        ctx.accounts.player_token_account.amount >= cost,
-        HorseGameError::InsufficientTokens
+        player.count_racing_horses() as usize + horse_indices.len()
+            <= player.stable.racing_slots as usize,
+        HorseGameError::StableCapacityExceeded
     );
 
-    player.stable.stable_type = stable_type;
-    player.stable.racing_slots = racing_slots;
-    player.stable.feed_capacity = feed_capacity;
-    player.last_upgrade_slot = slot;
-    player.total_tokens_spent = player.total_tokens_spent.saturating_add(cost);
+    let mut feed_added = 0u64;
+    let mut speed_added = 0u64;
+    for &index in &horse_indices {
+        require!(!player.is_horse_racing(index), HorseGameError::HorseIsRacing);
+        let horse = &player.horses[index as usize];
+        feed_added = safe_add_feed(feed_added, horse.stamina_cost as u64)?;
+        speed_added = safe_add_speed(speed_added, horse.speed as u64)?;
+    }
 
-    handle_fee_transfers(
-        player,
-        gs,
-        cost,
-        &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.fees_token_account.to_account_info(),
-        None,
-        &ctx.accounts.player_wallet.to_account_info(),
-        &ctx.accounts.token_program.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
-        false,
-        ctx.accounts.token_mint.decimals,
-    )?;
+    let new_player_feed = safe_add_feed(player.feed_consumption, feed_added)?;
+    let new_total_feed = safe_add_feed(gs.total_feed_consumption, feed_added)?;
+    let new_player_speed = safe_add_speed(player.total_speed, speed_added)?;
+    let new_total_speed = safe_add_speed(gs.total_speed, speed_added)?;
 
-    emit!(StableUpgraded {
-        player: ctx.accounts.player_wallet.key(),
-        new_stable_type: stable_type,
+    require!(
+        new_player_feed <= player.stable.feed_capacity,
+        HorseGameError::FeedCapacityExceeded
+    );
+
+    for &index in &horse_indices {
+        player.enter_horse_in_race(index)?;
+    }
+    player.feed_consumption = new_player_feed;
+    player.total_speed = new_player_speed;
+    player.total_races_entered = player
+        .total_races_entered
+        .saturating_add(horse_indices.len() as u64);
+    gs.total_feed_consumption = new_total_feed;
+    gs.total_speed = new_total_speed;
+
+    emit!(HorsesEnteredRaceBatch {
+        player: player.key(),
+        horse_indices,
+        speed_added,
+        feed_added,
     });
 
     Ok(())
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-/// CLAIM REWARDS
+/// WITHDRAW FROM RACE BATCH (unstake several horses at once)
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct ClaimRewards<'info> {
+pub struct WithdrawFromRaceBatch<'info> {
     #[account(mut)]
     pub player_wallet: Signer<'info>,
     #[account(
         mut,
         constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePendingBreeding,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -917,46 +1456,103 @@ pub struct ClaimRewards<'info> {
         bump,
     )]
     pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
-        constraint = player_token_account.owner == player_wallet.key(),
-        constraint = player_token_account.mint == global_state.token_mint
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
     )]
     pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
-    #[account()]
-    pub token_mint: InterfaceAccount<'info, Mint>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
 }
 
-pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
-    let now = Clock::get()?.slot;
+#[event]
+pub struct HorsesWithdrawnFromRaceBatch {
+    pub player: Pubkey,
+    pub horse_indices: Vec<u8>,
+    pub speed_removed: u64,
+    pub feed_removed: u64,
+}
+
+pub fn withdraw_from_race_batch(
+    ctx: Context<WithdrawFromRaceBatch>,
+    horse_indices: Vec<u8>,
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
 
     settle_and_mint_rewards(
-        &mut ctx.accounts.player,
-        &mut ctx.accounts.global_state,
-        now,
+        player,
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
         &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.token_mint,
         &ctx.accounts.rewards_vault,
         &ctx.accounts.token_program.to_account_info(),
         ctx.bumps.global_state,
-        ctx.accounts.token_mint.decimals,
     )?;
 
+    validate_horse_index_batch(
+        &horse_indices,
+        MAX_RACING_HORSES_PER_PLAYER as usize,
+        player.horse_count as usize,
+    )?;
+
+    let mut feed_removed = 0u64;
+    let mut speed_removed = 0u64;
+    for &index in &horse_indices {
+        require!(player.is_horse_racing(index), HorseGameError::HorseNotRacing);
+        let horse = &player.horses[index as usize];
+        feed_removed = safe_add_feed(feed_removed, horse.stamina_cost as u64)?;
+        speed_removed = safe_add_speed(speed_removed, horse.speed as u64)?;
+    }
+
+    let new_player_feed = safe_sub_feed(player.feed_consumption, feed_removed)?;
+    let new_total_feed = safe_sub_feed(gs.total_feed_consumption, feed_removed)?;
+    let new_player_speed = safe_sub_speed(player.total_speed, speed_removed)?;
+    let new_total_speed = safe_sub_speed(gs.total_speed, speed_removed)?;
+
+    for &index in &horse_indices {
+        player.withdraw_horse_from_race(index)?;
+    }
+    player.feed_consumption = new_player_feed;
+    player.total_speed = new_player_speed;
+    gs.total_feed_consumption = new_total_feed;
+    gs.total_speed = new_total_speed;
+
+    emit!(HorsesWithdrawnFromRaceBatch {
+        player: player.key(),
+        horse_indices,
+        speed_removed,
+        feed_removed,
+    });
+
     Ok(())
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-/// OPEN HORSE PACK (commit phase)
+/// OPTIMIZE LINEUP (re-enter the speed-maximizing set of horses)
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct OpenHorsePackCommit<'info> {
+pub struct OptimizeLineup<'info> {
     #[account(mut)]
     pub player_wallet: Signer<'info>,
     #[account(
         mut,
         constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
-        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePackAlreadyPending,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePendingBreeding,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -975,82 +1571,121 @@ pub struct OpenHorsePackCommit<'info> {
         bump,
     )]
     pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
     #[account(
         mut,
         constraint = player_token_account.mint == global_state.token_mint,
         constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
     )]
     pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
-    #[account(
-        mut,
-        constraint = fees_token_account.mint == global_state.token_mint,
-        constraint = fees_token_account.owner == global_state.fees_wallet @ HorseGameError::Unauthorized
-    )]
-    pub fees_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
-    #[account(mut)]
-    pub referrer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
-    #[account(mut)]
-    pub token_mint: InterfaceAccount<'info, Mint>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+#[event]
+pub struct LineupOptimized {
+    pub player: Pubkey,
+    pub horse_indices: Vec<u8>,
+    pub total_speed: u64,
+    pub feed_consumption: u64,
 }
 
-pub fn open_horse_pack_commit(ctx: Context<OpenHorsePackCommit>) -> Result<()> {
+pub fn optimize_lineup(ctx: Context<OptimizeLineup>) -> Result<()> {
     let slot = Clock::get()?.slot;
     let player = &mut ctx.accounts.player;
     let gs = &mut ctx.accounts.global_state;
 
-    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
-    require!(
-        (player.horse_count as usize) + 5 <= MAX_HORSES_PER_PLAYER as usize,
-        HorseGameError::StableCapacityExceeded
-    );
-
     settle_and_mint_rewards(
         player,
         gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
         slot,
         &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.token_mint,
         &ctx.accounts.rewards_vault,
         &ctx.accounts.token_program.to_account_info(),
         ctx.bumps.global_state,
-        ctx.accounts.token_mint.decimals,
     )?;
 
-    let pack_cost = gs.horse_pack_cost_microtokens;
+    let mut lineup = optimize_racing_lineup(
+        &player.horses[..player.horse_count as usize],
+        player.stable.racing_slots.min(MAX_RACING_HORSES_PER_PLAYER),
+        player.stable.feed_capacity,
+    );
 
-    handle_fee_transfers(
-        player,
-        gs,
-        pack_cost,
-        &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.fees_token_account.to_account_info(),
-        ctx.accounts.referrer_token_account.clone(),
-        &ctx.accounts.player_wallet.to_account_info(),
-        &ctx.accounts.token_program.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
-        true,
-        ctx.accounts.token_mint.decimals,
+    // A truncated search can miss a lineup the player already found; keep theirs
+    let optimized_speed: u64 = lineup
+        .iter()
+        .map(|&index| player.horses[index as usize].speed as u64)
+        .sum();
+    if player.total_speed >= optimized_speed {
+        lineup = (0..player.horse_count)
+            .filter(|&index| player.is_horse_racing(index))
+            .collect();
+    }
+
+    let mut new_bitset = 0u128;
+    let mut new_player_feed = 0u64;
+    let mut new_player_speed = 0u64;
+    let mut newly_entered = 0u64;
+    for &index in &lineup {
+        let horse = &player.horses[index as usize];
+        new_bitset |= 1u128 << index;
+        new_player_feed = safe_add_feed(new_player_feed, horse.stamina_cost as u64)?;
+        new_player_speed = safe_add_speed(new_player_speed, horse.speed as u64)?;
+        if !player.is_horse_racing(index) {
+            newly_entered += 1;
+        }
+    }
+
+    // Swap the player's old contribution for the new one in a single step
+    let new_total_feed = safe_add_feed(
+        safe_sub_feed(gs.total_feed_consumption, player.feed_consumption)?,
+        new_player_feed,
+    )?;
+    let new_total_speed = safe_add_speed(
+        safe_sub_speed(gs.total_speed, player.total_speed)?,
+        new_player_speed,
     )?;
 
-    player.pending_action = PendingRandomAction::HorsePack;
-    player.commit_slot = slot;
-    player.total_tokens_spent = player.total_tokens_spent.saturating_add(pack_cost);
+    player.racing_horses_bitset = new_bitset;
+    player.feed_consumption = new_player_feed;
+    player.total_speed = new_player_speed;
+    player.total_races_entered = player.total_races_entered.saturating_add(newly_entered);
+    gs.total_feed_consumption = new_total_feed;
+    gs.total_speed = new_total_speed;
+
+    emit!(LineupOptimized {
+        player: player.key(),
+        horse_indices: lineup,
+        total_speed: new_player_speed,
+        feed_consumption: new_player_feed,
+    });
 
     Ok(())
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-/// SETTLE OPEN HORSE PACK (reveal phase)
+/// UPGRADE STABLE
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct SettleOpenHorsePack<'info> {
+#[instruction(stable_type: u8)]
+pub struct UpgradeStable<'info> {
     #[account(mut)]
     pub player_wallet: Signer<'info>,
     #[account(
         mut,
         constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
-        constraint = player.pending_action == PendingRandomAction::HorsePack @ HorseGameError::NoHorsePackPending,
+        constraint = player.stable.stable_type + 1 == stable_type @ HorseGameError::InvalidStableType,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -1075,209 +1710,171 @@ pub struct SettleOpenHorsePack<'info> {
         constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
     )]
     pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
-    #[account()]
+    #[account(
+        mut,
+        constraint = fees_token_account.mint == global_state.token_mint,
+        constraint = fees_token_account.owner == global_state.fees_wallet @ HorseGameError::Unauthorized
+    )]
+    pub fees_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
     pub token_mint: InterfaceAccount<'info, Mint>,
     pub token_program: Interface<'info, TokenInterface>,
-    /// CHECK: Checked manually
-    pub slot_hashes: AccountInfo<'info>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+    /// Required once `global_state.stable_tiers_enabled` is set
+    #[account(seeds = [STABLE_TIERS_SEED], bump)]
+    pub stable_tiers: Option<Box<Account<'info, StableTierConfig>>>,
 }
 
-pub fn settle_open_horse_pack(ctx: Context<SettleOpenHorsePack>) -> Result<()> {
-    let clock: Clock = Clock::get()?;
+pub fn upgrade_stable(ctx: Context<UpgradeStable>, stable_type: u8) -> Result<()> {
+    let slot = Clock::get()?.slot;
     let player = &mut ctx.accounts.player;
     let gs = &mut ctx.accounts.global_state;
 
-    require!(
-        clock.slot >= player.commit_slot + MIN_RANDOMNESS_DELAY_SLOTS,
-        HorseGameError::RandomnessNotResolved
-    );
-    let reveal_slot = player.commit_slot + MIN_RANDOMNESS_DELAY_SLOTS;
-
-    let sysvar_slot_history = &ctx.accounts.slot_hashes;
-    require!(
-        sysvar_slot_history.key == &slot_hashes::id(),
-        HorseGameError::InvalidSlotHashes
-    );
-
-    let data = sysvar_slot_history.try_borrow_data()?;
-    let num_slot_hashes = u64::from_le_bytes(data[0..8].try_into().unwrap());
-    let mut pos = 8;
-    let mut found_hash = None;
-    for _ in 0..num_slot_hashes {
-        let slot = u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap());
-        pos += 8;
-        let hash = &data[pos..pos + 32];
-        if slot == reveal_slot {
-            found_hash = Some(hash);
-            break;
-        }
-        pos += 32;
-    }
-
-    let random_value = found_hash.ok_or(HorseGameError::SlotNotFound)?;
+    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
 
     settle_and_mint_rewards(
         player,
         gs,
-        clock.slot,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
         &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.token_mint,
         &ctx.accounts.rewards_vault,
         &ctx.accounts.token_program.to_account_info(),
         ctx.bumps.global_state,
-        ctx.accounts.token_mint.decimals,
     )?;
 
-    let mut horse_ids = [0u16; 5];
-    for i in 0..5 {
-        let slice_start = i * 4;
-        let slice_end = slice_start + 4;
-        let mut random_bytes: [u8; 4] = [0; 4];
-        random_bytes.copy_from_slice(&random_value[slice_start..slice_end]);
-        let random_u32 = u32::from_le_bytes(random_bytes);
-
-        let random_percent = (random_u32 as u64 * 1000 / (u32::MAX as u64 + 1)) as u32;
-
-        let grade = match random_percent {
-            0..=499 => GRADE_E,      // 50.0%
-            500..=749 => GRADE_D,    // 25.0%
-            750..=899 => GRADE_C,    // 15.0%
-            900..=989 => GRADE_B,    // 9.0%
-            _ => GRADE_SS,           // 1.0%
-        };
-
-        let horses_of_grade: Vec<&(u16, u8, u16, u8)> = HORSE_DATA
-            .iter()
-            .filter(|(_, horse_grade, _, _)| *horse_grade == grade)
-            .collect();
-
-        if !horses_of_grade.is_empty() {
-            let horse_index =
-                (random_u32 as u64 * horses_of_grade.len() as u64 / (u32::MAX as u64 + 1)) as usize;
-
-            let (horse_id, _, speed, stamina_cost) = horses_of_grade[horse_index];
+    let StableTier {
+        racing_slots,
+        feed_capacity,
+        cost,
+    } = stable_tier(gs, &ctx.accounts.stable_tiers, stable_type)?;
 
-            require!(
-                (player.horse_count as usize) < MAX_HORSES_PER_PLAYER as usize,
-                HorseGameError::StableCapacityExceeded
-            );
+    require!(
+        ctx.accounts.player_token_account.amount >= cost,
+        HorseGameError::InsufficientTokens
+    );
 
-            let new_horse = Horse {
-                id: *horse_id,
-                grade,
-                speed: *speed,
-                stamina_cost: *stamina_cost,
-            };
-            player.add_horse(new_horse)?;
-            horse_ids[i] = *horse_id;
-        }
-    }
+    player.stable.stable_type = stable_type;
+    player.stable.racing_slots = racing_slots;
+    player.stable.feed_capacity = feed_capacity;
+    player.last_upgrade_slot = slot;
+    player.total_tokens_spent = player.total_tokens_spent.saturating_add(cost);
 
-    player.pending_action = PendingRandomAction::None;
-    player.commit_slot = 0;
-    player.total_horse_packs_opened = player.total_horse_packs_opened.saturating_add(1);
-    gs.total_horse_packs_opened = gs.total_horse_packs_opened.saturating_add(1);
+    handle_fee_transfers(
+        player,
+        gs,
+        cost,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.fees_token_account.to_account_info(),
+        None,
+        &ctx.accounts.player_wallet.to_account_info(),
+        &ctx.accounts.token_program.to_account_info(),
+        &ctx.accounts.token_mint.to_account_info(),
+        false,
+        ctx.accounts.token_mint.decimals,
+    )?;
 
-    emit!(HorsePackOpened {
-        player: player.key(),
-        horse_ids: horse_ids.map(|id| id as u8),
+    emit!(StableUpgraded {
+        player: ctx.accounts.player_wallet.key(),
+        new_stable_type: stable_type,
     });
 
     Ok(())
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-/// ADMIN FUNCTIONS
+/// CLAIM REWARDS
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct ToggleProduction<'info> {
+pub struct ClaimRewards<'info> {
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
     #[account(
         mut,
         seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
         bump,
-        has_one = authority @ HorseGameError::Unauthorized
     )]
     pub global_state: Account<'info, GlobalState>,
-}
-
-pub fn toggle_production(ctx: Context<ToggleProduction>, enable: bool) -> Result<()> {
-    ctx.accounts.global_state.production_enabled = enable;
-    Ok(())
-}
-
-#[derive(Accounts)]
-pub struct UpdateParameters<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
     #[account(
         mut,
-        seeds = [GLOBAL_STATE_SEED],
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
         bump,
-        has_one = authority @ HorseGameError::Unauthorized
     )]
-    pub global_state: Account<'info, GlobalState>,
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.owner == player_wallet.key(),
+        constraint = player_token_account.mint == global_state.token_mint
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
 }
 
-pub fn update_parameter(
-    ctx: Context<UpdateParameters>,
-    parameter_index: u8,
-    parameter_value: u64,
-) -> Result<()> {
-    let gs = &mut ctx.accounts.global_state;
+pub fn claim_rewards(ctx: Context<ClaimRewards>) -> Result<()> {
+    let now = Clock::get()?.slot;
 
-    match parameter_index {
-        0 => {
-            require!(parameter_value <= 100, HorseGameError::InvalidReferralFee);
-            gs.referral_fee = parameter_value as u8;
-        }
-        1 => {
-            require!(parameter_value <= 100, HorseGameError::InvalidBurnRate);
-            gs.burn_rate = parameter_value as u8;
-        }
-        2 => {
-            require!(parameter_value > 0, HorseGameError::InvalidDustThresholdDivisor);
-            gs.dust_threshold_divisor = parameter_value;
-        }
-        3 => gs.initial_stable_purchase_fee_lamports = parameter_value,
-        4 => gs.horse_pack_cost_microtokens = parameter_value,
-        5 => gs.gamble_fee_lamports = parameter_value,
-        6 => gs.staking_lockup_slots = parameter_value,
-        7 => gs.token_reward_rate = parameter_value,
-        8 => gs.reward_rate = parameter_value,
-        _ => return err!(HorseGameError::InvalidParameterIndex),
-    }
+    settle_and_mint_rewards(
+        &mut ctx.accounts.player,
+        &mut ctx.accounts.global_state,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        now,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
 
     Ok(())
 }
 
+/// ────────────────────────────────────────────────────────────────────────────
+/// OPEN HORSE PACK (commit phase)
+/// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct UpdatePool<'info> {
+#[instruction(pack_type: u16)]
+pub struct OpenHorsePackCommit<'info> {
     #[account(mut)]
-    pub authority: Signer<'info>,
+    pub player_wallet: Signer<'info>,
     #[account(
         mut,
-        seeds = [GLOBAL_STATE_SEED],
-        bump,
-        has_one = authority @ HorseGameError::Unauthorized
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePackAlreadyPending,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
     )]
-    pub global_state: Account<'info, GlobalState>,
-}
-
-pub fn update_pool_manual(ctx: Context<UpdatePool>) -> Result<()> {
-    let slot_now: u64 = Clock::get()?.slot;
-    update_pool(&mut ctx.accounts.global_state, slot_now);
-    Ok(())
-}
-
-#[derive(Accounts)]
-pub struct ResetPlayer<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
+    pub player: Box<Account<'info, Player>>,
     #[account(
         mut,
-        has_one = authority @ HorseGameError::Unauthorized,
         seeds = [GLOBAL_STATE_SEED],
         constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
         constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
@@ -1286,60 +1883,196 @@ pub struct ResetPlayer<'info> {
     pub global_state: Account<'info, GlobalState>,
     #[account(
         mut,
-        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
-        bump
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
     )]
-    pub player: Box<Account<'info, Player>>,
-    #[account()]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = fees_token_account.mint == global_state.token_mint,
+        constraint = fees_token_account.owner == global_state.fees_wallet @ HorseGameError::Unauthorized
+    )]
+    pub fees_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub referrer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
     pub token_mint: InterfaceAccount<'info, Mint>,
-    /// CHECK: System account
-    pub player_wallet: AccountInfo<'info>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+    /// Required for every pack type except the built-in one
+    #[account(
+        mut,
+        seeds = [PACK_TYPE_SEED, pack_type.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pack: Option<Box<Account<'info, PackType>>>,
+    /// CHECK: This is the fees recipient wallet; required for packs with a SOL price
+    #[account(
+        mut,
+        constraint = fees_wallet.key() == global_state.fees_wallet @ HorseGameError::Unauthorized
+    )]
+    pub fees_wallet: Option<AccountInfo<'info>>,
+    #[account(
+        mut,
+        seeds = [SOL_REWARDS_WALLET_SEED],
+        bump
+    )]
+    pub sol_rewards_wallet: Option<Box<Account<'info, SolRewardsWallet>>>,
+    pub system_program: Option<Program<'info, System>>,
 }
 
-pub fn reset_player(ctx: Context<ResetPlayer>) -> Result<()> {
+pub fn open_horse_pack_commit(
+    ctx: Context<OpenHorsePackCommit>,
+    pack_type: u16,
+    secret_hash: [u8; 32],
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
     let player = &mut ctx.accounts.player;
     let gs = &mut ctx.accounts.global_state;
-    let slot = Clock::get()?.slot;
 
-    update_pool(gs, slot);
+    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
+    require!(secret_hash != [0; 32], HorseGameError::InvalidEntropySecret);
+
+    let (pack_cost, sol_price, pack_size, grade_weights) = if pack_type == DEFAULT_PACK_TYPE_ID {
+        (
+            gs.horse_pack_cost_microtokens,
+            0,
+            DEFAULT_PACK_HORSE_COUNT,
+            DEFAULT_PACK_GRADE_WEIGHTS,
+        )
+    } else {
+        let pack = ctx
+            .accounts
+            .pack
+            .as_deref_mut()
+            .ok_or(HorseGameError::PackTypeMissing)?;
+        require!(pack.active, HorseGameError::PackTypeInactive);
+        if let Some(cap) = pack.supply_cap {
+            require!(pack.opened < cap, HorseGameError::PackSoldOut);
+        }
+        pack.opened += 1;
+        (
+            pack.price_microtokens,
+            pack.sol_price_lamports.unwrap_or(0),
+            pack.horse_count,
+            pack.grade_weights,
+        )
+    };
 
-    let old_feed = player.feed_consumption;
-    let old_speed = player.total_speed;
+    require!(
+        (player.horse_count as usize) + pack_size as usize <= MAX_HORSES_PER_PLAYER as usize,
+        HorseGameError::StableCapacityExceeded
+    );
 
-    player.feed_consumption = 0;
-    player.total_speed = 0;
-    let (racing_slots, feed_capacity, _) = STABLE_CONFIGS[0];
-    player.stable = Stable {
-        stable_type: 0,
-        racing_slots,
-        feed_capacity,
-    };
-    player.horses = [Horse::default(); MAX_HORSES_PER_PLAYER as usize];
-    player.horse_count = 0;
-    player.racing_horses_bitset = 0;
+    settle_and_mint_rewards(
+        player,
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
 
-    gs.total_feed_consumption = gs.total_feed_consumption.saturating_sub(old_feed);
-    gs.total_speed = gs.total_speed.saturating_sub(old_speed);
+    if pack_cost > 0 {
+        handle_fee_transfers(
+            player,
+            gs,
+            pack_cost,
+            &ctx.accounts.player_token_account.to_account_info(),
+            &ctx.accounts.fees_token_account.to_account_info(),
+            ctx.accounts.referrer_token_account.clone(),
+            &ctx.accounts.player_wallet.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.token_mint.to_account_info(),
+            true,
+            ctx.accounts.token_mint.decimals,
+        )?;
+    }
 
-    player.last_claim_slot = slot;
-    player.last_acc_tokens_per_speed = gs.acc_tokens_per_speed;
-    player.pending_action = PendingRandomAction::None;
-    player.commit_slot = 0;
+    if sol_price > 0 {
+        let (Some(fees_wallet), Some(sol_rewards_wallet), Some(system_program)) = (
+            ctx.accounts.fees_wallet.as_ref(),
+            ctx.accounts.sol_rewards_wallet.as_ref(),
+            ctx.accounts.system_program.as_ref(),
+        ) else {
+            return err!(HorseGameError::SolPaymentAccountsMissing);
+        };
+
+        // SOL price, with the stakers' share routed to the SOL rewards wallet
+        let (stakers_share, fees_share) = split_sol_fee(gs, sol_price);
+
+        if stakers_share > 0 {
+            deposit_sol_rewards_to_stakers(
+                gs,
+                &ctx.accounts.player_wallet.to_account_info(),
+                &sol_rewards_wallet.to_account_info(),
+                &system_program.to_account_info(),
+                stakers_share,
+            )?;
+        }
+
+        if fees_share > 0 {
+            anchor_lang::system_program::transfer(
+                CpiContext::new(
+                    system_program.to_account_info(),
+                    anchor_lang::system_program::Transfer {
+                        from: ctx.accounts.player_wallet.to_account_info(),
+                        to: fees_wallet.clone(),
+                    },
+                ),
+                fees_share,
+            )?;
+        }
+
+        player.total_sol_spent = player.total_sol_spent.saturating_add(sol_price);
+    }
+
+    // Snapshot the pack so edits before the settle can't change what was bought
+    player.pending_action = PendingRandomAction::PackOpening {
+        pack_type,
+        horse_count: pack_size,
+        grade_weights,
+    };
+    player.commit_slot = slot;
+    player.pending_randomness_source = gs.randomness_source;
+    player.pending_oracle_index = gs.randomness_oracle_index;
+    player.entropy_commitment = secret_hash;
+    player.total_tokens_spent = player.total_tokens_spent.saturating_add(pack_cost);
 
     Ok(())
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-/// BREED HORSES (commit phase)
+/// SETTLE OPEN HORSE PACK (reveal phase)
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct BreedHorsesCommit<'info> {
+pub struct SettleOpenHorsePack<'info> {
     #[account(mut)]
     pub player_wallet: Signer<'info>,
     #[account(
         mut,
         constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
-        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::BreedingAlreadyPending,
+        constraint = matches!(
+            player.pending_action,
+            PendingRandomAction::HorsePack | PendingRandomAction::PackOpening { .. }
+        ) @ HorseGameError::NoHorsePackPending,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -1367,257 +2100,3744 @@ pub struct BreedHorsesCommit<'info> {
     #[account()]
     pub token_mint: InterfaceAccount<'info, Mint>,
     pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Checked manually
+    pub slot_hashes: AccountInfo<'info>,
+    /// CHECK: Oracle request account, checked by `OracleRandomness`; required
+    /// for actions committed while `global_state.randomness_source` is the oracle
+    pub randomness_oracle: Option<AccountInfo<'info>>,
+    /// Required with `randomness_oracle`
+    #[account(seeds = [RANDOMNESS_ORACLES_SEED], bump)]
+    pub randomness_oracles: Option<Box<Account<'info, RandomnessOracles>>>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+    /// Required once `global_state.horse_catalog_enabled` is set
+    #[account(mut, seeds = [HORSE_CATALOG_SEED], bump)]
+    pub horse_catalog: Option<Box<Account<'info, HorseCatalog>>>,
 }
 
-pub fn breed_horses_commit(ctx: Context<BreedHorsesCommit>, horse_indices: Vec<u8>) -> Result<()> {
-    let slot = Clock::get()?.slot;
+pub fn settle_open_horse_pack(ctx: Context<SettleOpenHorsePack>, secret: [u8; 32]) -> Result<()> {
+    let clock: Clock = Clock::get()?;
     let player = &mut ctx.accounts.player;
     let gs = &mut ctx.accounts.global_state;
 
-    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
-    require!(
-        !horse_indices.is_empty() && horse_indices.len() <= 128,
-        HorseGameError::InvalidBreedingHorseCount
-    );
-    require!(
-        player.horse_count as usize >= horse_indices.len(),
-        HorseGameError::InvalidBreedingHorseCount
-    );
+    let secret = revealed_secret(&player.entropy_commitment, &secret)?;
+    let reveal = reveal_randomness(
+        player,
+        &ctx.accounts.slot_hashes,
+        &ctx.accounts.randomness_oracle,
+        &ctx.accounts.randomness_oracles,
+        clock.slot,
+    )?;
 
     settle_and_mint_rewards(
         player,
         gs,
-        slot,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        clock.slot,
         &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
+        &ctx.accounts.token_mint,
         &ctx.accounts.rewards_vault,
         &ctx.accounts.token_program.to_account_info(),
         ctx.bumps.global_state,
-        ctx.accounts.token_mint.decimals,
     )?;
 
-    let mut sorted_indices = horse_indices.clone();
-    sorted_indices.sort();
-    for i in 1..sorted_indices.len() {
-        require!(
-            sorted_indices[i] != sorted_indices[i - 1],
-            HorseGameError::DuplicateBreedingHorseIndices
+    let (pack_size, grade_weights) = match player.pending_action {
+        PendingRandomAction::PackOpening {
+            horse_count,
+            grade_weights,
+            ..
+        } => (horse_count, grade_weights),
+        // Committed before pack types existed, so always the built-in pack
+        _ => (DEFAULT_PACK_HORSE_COUNT, DEFAULT_PACK_GRADE_WEIGHTS),
+    };
+
+    let mut rng = RandomStream::with_secret(&reveal.value, &secret, &player.key());
+
+    let mut horses = Vec::with_capacity(pack_size as usize);
+    for _ in 0..pack_size {
+        let grade = apply_pity(
+            roll_grade(rng.next_u32(), &grade_weights),
+            player.pity_counter,
+            gs.pity_b_threshold,
+            gs.pity_ss_threshold,
         );
+        player.pity_counter = if grade == GRADE_SS {
+            0
+        } else {
+            player.pity_counter.saturating_add(1)
+        };
+
+        let drawn = draw_horse(gs, &mut ctx.accounts.horse_catalog, grade, |pool_len| {
+            rng.below(pool_len as u64) as usize
+        })?;
+
+        if let Some(new_horse) = drawn {
+            require!(
+                (player.horse_count as usize) < MAX_HORSES_PER_PLAYER as usize,
+                HorseGameError::StableCapacityExceeded
+            );
+
+            horses.push(PackHorse {
+                horse_index: player.horse_count,
+                id: new_horse.id,
+                grade: new_horse.grade,
+                speed: new_horse.speed,
+                stamina_cost: new_horse.stamina_cost,
+            });
+            player.add_horse(new_horse)?;
+        }
     }
-    for &index in &horse_indices {
-        validate_horse_index(index, player.horse_count as usize)?;
-        require!(!player.is_horse_racing(index), HorseGameError::HorseIsRacing);
+
+    let pack_type = player.pending_action.pack_type();
+    player.pending_action = PendingRandomAction::None;
+    player.commit_slot = 0;
+    player.entropy_commitment = [0; 32];
+    player.total_horse_packs_opened = player.total_horse_packs_opened.saturating_add(1);
+    gs.total_horse_packs_opened = gs.total_horse_packs_opened.saturating_add(1);
+
+    emit!(HorsePackOpened {
+        player: player.key(),
+        pack_type,
+        horses,
+        reveal_slot: reveal.slot,
+        slot_hash: reveal.value,
+        secret,
+        pity_counter: player.pity_counter,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// ADMIN FUNCTIONS
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct ToggleProduction<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+}
+
+pub fn toggle_production(ctx: Context<ToggleProduction>, enable: bool) -> Result<()> {
+    ctx.accounts.global_state.production_enabled = enable;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        // ConfigUpdate: Option<Pubkey> + 3 x Option<u8> + 6 x Option<u64> + 5 x Option<u16>
+        space = 8 + (33 + 3 * 2 + 6 * 9 + 5 * 3) + 8 + 8 + 32,
+        seeds = [PENDING_PARAMETER_SEED],
+        bump
+    )]
+    pub pending_change: Box<Account<'info, PendingParameterChange>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct ConfigUpdateQueued {
+    pub update: ConfigUpdate,
+    pub effective_slot: u64,
+}
+
+/// Values before and after an applied update; only the updated fields are `Some`
+#[event]
+pub struct ConfigUpdated {
+    pub previous: ConfigUpdate,
+    pub updated: ConfigUpdate,
+}
+
+#[event]
+pub struct ConfigUpdateCancelled {
+    pub update: ConfigUpdate,
+}
+
+/// Queues a config update that `apply_parameter_change` can apply after
+/// `PARAMETER_TIMELOCK_SLOTS`; queuing again replaces it and restarts the delay
+pub fn update_config(ctx: Context<UpdateConfig>, update: ConfigUpdate) -> Result<()> {
+    // Reject bad values now rather than when the update is applied
+    let mut preview = (*ctx.accounts.global_state).clone();
+    apply_config_update(&mut preview, &update)?;
+
+    let slot = Clock::get()?.slot;
+    let effective_slot = slot.saturating_add(PARAMETER_TIMELOCK_SLOTS);
+    let pending = &mut ctx.accounts.pending_change;
+    pending.update = update.clone();
+    pending.queued_slot = slot;
+    pending.effective_slot = effective_slot;
+    pending.queued_by = ctx.accounts.authority.key();
+
+    emit!(ConfigUpdateQueued {
+        update,
+        effective_slot,
+    });
+
+    Ok(())
+}
+
+/// Writes every `Some` field of `update` and validates the resulting config as a
+/// whole; returns the values that were replaced
+fn apply_config_update(gs: &mut GlobalState, update: &ConfigUpdate) -> Result<ConfigUpdate> {
+    let previous = ConfigUpdate {
+        fees_wallet: update.fees_wallet.map(|_| gs.fees_wallet),
+        burn_rate: update.burn_rate.map(|_| gs.burn_rate),
+        referral_fee: update.referral_fee.map(|_| gs.referral_fee),
+        sol_rewards_share: update.sol_rewards_share.map(|_| gs.sol_rewards_share),
+        dust_threshold_divisor: update.dust_threshold_divisor.map(|_| gs.dust_threshold_divisor),
+        initial_stable_purchase_fee_lamports: update
+            .initial_stable_purchase_fee_lamports
+            .map(|_| gs.initial_stable_purchase_fee_lamports),
+        horse_pack_cost_microtokens: update
+            .horse_pack_cost_microtokens
+            .map(|_| gs.horse_pack_cost_microtokens),
+        gamble_fee_lamports: update.gamble_fee_lamports.map(|_| gs.gamble_fee_lamports),
+        staking_lockup_slots: update.staking_lockup_slots.map(|_| gs.staking_lockup_slots),
+        token_reward_rate: update.token_reward_rate.map(|_| gs.token_reward_rate),
+        gamble_win_chance_bps: update.gamble_win_chance_bps.map(|_| gs.gamble_win_chance_bps),
+        gamble_house_edge_bps: update.gamble_house_edge_bps.map(|_| gs.gamble_house_edge_bps),
+        marketplace_fee_bps: update.marketplace_fee_bps.map(|_| gs.marketplace_fee_bps),
+        pity_b_threshold: update.pity_b_threshold.map(|_| gs.pity_b_threshold),
+        pity_ss_threshold: update.pity_ss_threshold.map(|_| gs.pity_ss_threshold),
+    };
+
+    if let Some(value) = update.fees_wallet {
+        gs.fees_wallet = value;
+    }
+    if let Some(value) = update.burn_rate {
+        gs.burn_rate = value;
+    }
+    if let Some(value) = update.referral_fee {
+        gs.referral_fee = value;
+    }
+    if let Some(value) = update.sol_rewards_share {
+        gs.sol_rewards_share = value;
+    }
+    if let Some(value) = update.dust_threshold_divisor {
+        gs.dust_threshold_divisor = value;
+    }
+    if let Some(value) = update.initial_stable_purchase_fee_lamports {
+        gs.initial_stable_purchase_fee_lamports = value;
+    }
+    if let Some(value) = update.horse_pack_cost_microtokens {
+        gs.horse_pack_cost_microtokens = value;
+    }
+    if let Some(value) = update.gamble_fee_lamports {
+        gs.gamble_fee_lamports = value;
+    }
+    if let Some(value) = update.staking_lockup_slots {
+        gs.staking_lockup_slots = value;
+    }
+    if let Some(value) = update.token_reward_rate {
+        gs.token_reward_rate = value;
+    }
+    if let Some(value) = update.gamble_win_chance_bps {
+        gs.gamble_win_chance_bps = value;
+    }
+    if let Some(value) = update.gamble_house_edge_bps {
+        gs.gamble_house_edge_bps = value;
+    }
+    if let Some(value) = update.marketplace_fee_bps {
+        gs.marketplace_fee_bps = value;
+    }
+    if let Some(value) = update.pity_b_threshold {
+        gs.pity_b_threshold = value;
+    }
+    if let Some(value) = update.pity_ss_threshold {
+        gs.pity_ss_threshold = value;
     }
 
-    let mut horse_indices_array = [0u8; 128];
-    for (i, &index) in horse_indices.iter().enumerate() {
-        horse_indices_array[i] = index;
+    // Validate the combined result so related fields are checked against each other
+    require!(
+        gs.fees_wallet != Pubkey::default(),
+        HorseGameError::InvalidFeesWallet
+    );
+    require!(gs.burn_rate <= 100, HorseGameError::InvalidBurnRate);
+    require!(gs.referral_fee <= 100, HorseGameError::InvalidReferralFee);
+    require!(
+        gs.sol_rewards_share <= 100,
+        HorseGameError::InvalidSolRewardsShare
+    );
+    require!(
+        gs.dust_threshold_divisor > 0,
+        HorseGameError::InvalidDustThresholdDivisor
+    );
+    // Only when touched: deployments upgraded from before the odds existed hold
+    // zeroed odds, which would otherwise block every unrelated update
+    if update.gamble_win_chance_bps.is_some() || update.gamble_house_edge_bps.is_some() {
+        validate_gamble_odds(
+            gs.gamble_win_chance_bps as u64,
+            gs.gamble_house_edge_bps as u64,
+        )?;
     }
+    require!(
+        gs.marketplace_fee_bps as u64 <= BPS_DENOMINATOR,
+        HorseGameError::InvalidMarketplaceFee
+    );
+    // Soft pity must kick in before the hard SS guarantee
+    require!(
+        gs.pity_b_threshold == 0
+            || gs.pity_ss_threshold == 0
+            || gs.pity_b_threshold < gs.pity_ss_threshold,
+        HorseGameError::InvalidPityThresholds
+    );
 
-    player.pending_action = PendingRandomAction::Breeding {
-        horse_indices: horse_indices_array,
-        horse_count: horse_indices.len() as u8,
-    };
-    player.commit_slot = slot;
+    Ok(previous)
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// APPLY PARAMETER CHANGE (permissionless crank once the timelock expires)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct ApplyParameterChange<'info> {
+    pub cranker: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        close = rent_receiver,
+        seeds = [PENDING_PARAMETER_SEED],
+        bump
+    )]
+    pub pending_change: Box<Account<'info, PendingParameterChange>>,
+    /// CHECK: Refunded the rent paid when the update was queued
+    #[account(
+        mut,
+        constraint = rent_receiver.key() == pending_change.queued_by @ HorseGameError::Unauthorized
+    )]
+    pub rent_receiver: AccountInfo<'info>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+pub fn apply_parameter_change(ctx: Context<ApplyParameterChange>) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let pending = &ctx.accounts.pending_change;
+    require!(
+        slot >= pending.effective_slot,
+        HorseGameError::ParameterChangeNotReady
+    );
+
+    // Close both pools at the old rate and dust threshold before they change
+    let gs = &mut ctx.accounts.global_state;
+    update_pool(
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
+    )?;
+    update_staking_pool(gs, slot);
+
+    // Revalidated: other fields may have changed since this was queued
+    let previous = apply_config_update(&mut ctx.accounts.global_state, &pending.update)?;
+
+    emit!(ConfigUpdated {
+        previous,
+        updated: pending.update.clone(),
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// CANCEL PARAMETER CHANGE (admin)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct CancelParameterChange<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        close = authority,
+        seeds = [PENDING_PARAMETER_SEED],
+        bump
+    )]
+    pub pending_change: Box<Account<'info, PendingParameterChange>>,
+}
+
+pub fn cancel_parameter_change(ctx: Context<CancelParameterChange>) -> Result<()> {
+    emit!(ConfigUpdateCancelled {
+        update: ctx.accounts.pending_change.update.clone(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct UpdatePool<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+pub fn update_pool_manual(ctx: Context<UpdatePool>) -> Result<()> {
+    let slot_now: u64 = Clock::get()?.slot;
+    update_pool(
+        &mut ctx.accounts.global_state,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot_now,
+    )?;
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct ResetPlayer<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        has_one = authority @ HorseGameError::Unauthorized,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    /// CHECK: System account
+    pub player_wallet: AccountInfo<'info>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+pub fn reset_player(ctx: Context<ResetPlayer>) -> Result<()> {
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+    let slot = Clock::get()?.slot;
+
+    update_pool(
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
+    )?;
+
+    let old_feed = player.feed_consumption;
+    let old_speed = player.total_speed;
+
+    player.feed_consumption = 0;
+    player.total_speed = 0;
+    let (racing_slots, feed_capacity, _) = STABLE_CONFIGS[0];
+    player.stable = Stable {
+        stable_type: 0,
+        racing_slots,
+        feed_capacity,
+    };
+    player.horses = [Horse::default(); MAX_HORSES_PER_PLAYER as usize];
+    player.horse_count = 0;
+    player.racing_horses_bitset = 0;
+
+    gs.total_feed_consumption = gs.total_feed_consumption.saturating_sub(old_feed);
+    gs.total_speed = gs.total_speed.saturating_sub(old_speed);
+
+    player.last_claim_slot = slot;
+    player.last_acc_tokens_per_speed = gs.acc_tokens_per_speed;
+    player.pending_action = PendingRandomAction::None;
+    player.commit_slot = 0;
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// BREED HORSES (commit phase)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct BreedHorsesCommit<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::BreedingAlreadyPending,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+/// Fusion recipe: 2-5 non-racing horses of one grade below SS, all consumed for
+/// a single next-grade roll whose odds grow with the number of inputs
+pub fn breed_horses_commit(
+    ctx: Context<BreedHorsesCommit>,
+    horse_indices: Vec<u8>,
+    secret_hash: [u8; 32],
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
+    require!(secret_hash != [0; 32], HorseGameError::InvalidEntropySecret);
+    // Inputs are removed at settle, so they must be free to leave now
+    player.require_horses_unlocked(slot)?;
+    require!(
+        (FUSION_MIN_INPUTS..=FUSION_MAX_INPUTS).contains(&horse_indices.len()),
+        HorseGameError::InvalidBreedingHorseCount
+    );
+    require!(
+        player.horse_count as usize >= horse_indices.len(),
+        HorseGameError::InvalidBreedingHorseCount
+    );
+
+    settle_and_mint_rewards(
+        player,
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    let mut sorted_indices = horse_indices.clone();
+    sorted_indices.sort();
+    for i in 1..sorted_indices.len() {
+        require!(
+            sorted_indices[i] != sorted_indices[i - 1],
+            HorseGameError::DuplicateBreedingHorseIndices
+        );
+    }
+    for &index in &horse_indices {
+        validate_horse_index(index, player.horse_count as usize)?;
+        require!(!player.is_horse_racing(index), HorseGameError::HorseIsRacing);
+    }
+
+    let grade = player.horses[horse_indices[0] as usize].grade;
+    require!(
+        horse_indices
+            .iter()
+            .all(|&index| player.horses[index as usize].grade == grade),
+        HorseGameError::FusionGradeMismatch
+    );
+    require!(
+        get_next_grade(grade).is_some() && fusion_chance_bps(grade, horse_indices.len()) > 0,
+        HorseGameError::FusionDisabled
+    );
+
+    let mut horse_indices_array = [0u8; 128];
+    for (i, &index) in horse_indices.iter().enumerate() {
+        horse_indices_array[i] = index;
+    }
+
+    player.pending_action = PendingRandomAction::Breeding {
+        horse_indices: horse_indices_array,
+        horse_count: horse_indices.len() as u8,
+    };
+    player.commit_slot = slot;
+    player.pending_randomness_source = gs.randomness_source;
+    player.pending_oracle_index = gs.randomness_oracle_index;
+    player.entropy_commitment = secret_hash;
+
+    gs.total_breeding_attempts = gs.total_breeding_attempts.saturating_add(1);
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// BREED HORSES (settle phase)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct BreedHorsesSettle<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = matches!(player.pending_action, PendingRandomAction::Breeding { .. }) @ HorseGameError::NoBreedingPending,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Checked manually
+    pub slot_hashes: AccountInfo<'info>,
+    /// CHECK: Oracle request account, checked by `OracleRandomness`; required
+    /// for actions committed while `global_state.randomness_source` is the oracle
+    pub randomness_oracle: Option<AccountInfo<'info>>,
+    /// Required with `randomness_oracle`
+    #[account(seeds = [RANDOMNESS_ORACLES_SEED], bump)]
+    pub randomness_oracles: Option<Box<Account<'info, RandomnessOracles>>>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+    /// Required once `global_state.horse_catalog_enabled` is set
+    #[account(mut, seeds = [HORSE_CATALOG_SEED], bump)]
+    pub horse_catalog: Option<Box<Account<'info, HorseCatalog>>>,
+}
+
+pub fn breed_horses_settle(ctx: Context<BreedHorsesSettle>, secret: [u8; 32]) -> Result<()> {
+    let clock: Clock = Clock::get()?;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    let secret = revealed_secret(&player.entropy_commitment, &secret)?;
+    let reveal = reveal_randomness(
+        player,
+        &ctx.accounts.slot_hashes,
+        &ctx.accounts.randomness_oracle,
+        &ctx.accounts.randomness_oracles,
+        clock.slot,
+    )?;
+
+    settle_and_mint_rewards(
+        player,
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        clock.slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    let (horse_indices_array, horse_count) = if let PendingRandomAction::Breeding {
+        horse_indices,
+        horse_count,
+    } = player.pending_action
+    {
+        (horse_indices, horse_count)
+    } else {
+        return Err(HorseGameError::NoBreedingPending.into());
+    };
+
+    let mut successful_offspring = 0u8;
+    let mut new_horses: Vec<Horse> = Vec::new();
+
+    let mut rng = RandomStream::with_secret(&reveal.value, &secret, &player.key());
+
+    // Commits made before secrets (zero commitment) predate fusion recipes:
+    // up to 128 inputs of any grade, each rolling on its own for a horse of
+    // the next grade (SS for SS), as they were promised at commit
+    let legacy = player.entropy_commitment == [0; 32];
+    let rolls: Vec<(u8, u32)> = if legacy {
+        horse_indices_array[..horse_count as usize]
+            .iter()
+            .filter(|&&index| index < player.horse_count)
+            .map(|&index| {
+                let grade = player.horses[index as usize].grade;
+                (get_next_grade(grade).unwrap_or(grade), LEGACY_FUSION_CHANCE_BPS)
+            })
+            .collect()
+    } else {
+        // Checked at commit to share one fusable grade, and horses cannot
+        // move while the action is pending
+        let grade = player.horses[horse_indices_array[0] as usize].grade;
+        get_next_grade(grade)
+            .map(|next_grade| (next_grade, fusion_chance_bps(grade, horse_count as usize)))
+            .into_iter()
+            .collect()
+    };
+
+    for (next_grade, chance_bps) in rolls {
+        if !rng.chance_bps(chance_bps) {
+            continue;
+        }
+        let drawn = draw_horse(gs, &mut ctx.accounts.horse_catalog, next_grade, |pool_len| {
+            rng.below(pool_len as u64) as usize
+        })?;
+
+        if let Some(offspring) = drawn {
+            new_horses.push(offspring);
+            successful_offspring += 1;
+        }
+    }
+
+    let indices_to_remove: Vec<u8> = horse_indices_array[0..horse_count as usize].to_vec();
+    player.batch_remove_horses(&indices_to_remove)?;
+
+    for new_horse in new_horses {
+        require!(
+            (player.horse_count as usize) < MAX_HORSES_PER_PLAYER as usize,
+            HorseGameError::StableCapacityExceeded
+        );
+
+        player.add_horse(new_horse)?;
+    }
+
+    player.pending_action = PendingRandomAction::None;
+    player.commit_slot = 0;
+    player.entropy_commitment = [0; 32];
+
+    player.total_horses_bred = player
+        .total_horses_bred
+        .saturating_add(horse_count as u64);
+
+    if successful_offspring > 0 {
+        player.successful_breeding = player
+            .successful_breeding
+            .saturating_add(successful_offspring as u64);
+        gs.total_successful_breeding = gs
+            .total_successful_breeding
+            .saturating_add(successful_offspring as u64);
+    }
+
+    emit!(HorsesBred {
+        player: player.key(),
+        successful_offspring,
+        total_bred: horse_count,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// BREED PAIR (commit phase)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct BreedPairCommit<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::BreedingAlreadyPending,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = fees_token_account.mint == global_state.token_mint,
+        constraint = fees_token_account.owner == global_state.fees_wallet @ HorseGameError::Unauthorized
+    )]
+    pub fees_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    #[account(mut, seeds = [BREEDING_CONFIG_SEED], bump)]
+    pub breeding_config: Box<Account<'info, BreedingConfig>>,
+    #[account(
+        init_if_needed,
+        payer = player_wallet,
+        space = 8 + 8,
+        seeds = [BREEDER_SEED, player.key().as_ref()],
+        bump
+    )]
+    pub breeder: Box<Account<'info, Breeder>>,
+    pub system_program: Program<'info, System>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+/// Pays the fee for the foal's grade (the lower parent grade); both parents
+/// stay in the stable whatever the outcome, so a stable may only pair once per
+/// `pair_cooldown_slots`
+pub fn breed_pair_commit(
+    ctx: Context<BreedPairCommit>,
+    sire_index: u8,
+    dam_index: u8,
+    secret_hash: [u8; 32],
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
+    require!(secret_hash != [0; 32], HorseGameError::InvalidEntropySecret);
+    require!(
+        sire_index != dam_index,
+        HorseGameError::DuplicateBreedingHorseIndices
+    );
+    validate_horse_index(sire_index, player.horse_count as usize)?;
+    validate_horse_index(dam_index, player.horse_count as usize)?;
+    require!(
+        (player.horse_count as usize) < MAX_HORSES_PER_PLAYER as usize,
+        HorseGameError::StableCapacityExceeded
+    );
+
+    let grade = player.horses[sire_index as usize]
+        .grade
+        .min(player.horses[dam_index as usize].grade) as usize;
+    let config = &mut ctx.accounts.breeding_config;
+    let fee = config.pair_fee_microtokens[grade];
+    let success_bps = config.pair_success_bps[grade];
+    require!(success_bps > 0, HorseGameError::PairBreedingDisabled);
+
+    let breeder = &mut ctx.accounts.breeder;
+    require!(
+        breeder.last_pair_commit_slot == 0
+            || slot >= breeder.last_pair_commit_slot.saturating_add(config.pair_cooldown_slots),
+        HorseGameError::PairBreedingCooldown
+    );
+    breeder.last_pair_commit_slot = slot;
+
+    // Reserved now so the settle knows its record address; a failed or
+    // cancelled pairing just leaves the id unused
+    let foal_id = config.reserve_foal_id()?;
+
+    settle_and_mint_rewards(
+        player,
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    if fee > 0 {
+        handle_fee_transfers(
+            player,
+            gs,
+            fee,
+            &ctx.accounts.player_token_account.to_account_info(),
+            &ctx.accounts.fees_token_account.to_account_info(),
+            None,
+            &ctx.accounts.player_wallet.to_account_info(),
+            &ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.token_mint.to_account_info(),
+            false,
+            ctx.accounts.token_mint.decimals,
+        )?;
+    }
+
+    player.pending_action = PendingRandomAction::PairBreeding {
+        sire_index,
+        dam_index,
+        success_bps,
+        foal_id,
+    };
+    player.commit_slot = slot;
+    player.pending_randomness_source = gs.randomness_source;
+    player.pending_oracle_index = gs.randomness_oracle_index;
+    player.entropy_commitment = secret_hash;
+    player.total_tokens_spent = player.total_tokens_spent.saturating_add(fee);
+
+    gs.total_breeding_attempts = gs.total_breeding_attempts.saturating_add(1);
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// BREED PAIR (settle phase)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct BreedPairSettle<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = matches!(player.pending_action, PendingRandomAction::PairBreeding { .. }) @ HorseGameError::NoBreedingPending,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Checked manually
+    pub slot_hashes: AccountInfo<'info>,
+    /// CHECK: Oracle request account, checked by `OracleRandomness`; required
+    /// for actions committed while `global_state.randomness_source` is the oracle
+    pub randomness_oracle: Option<AccountInfo<'info>>,
+    /// Required with `randomness_oracle`
+    #[account(seeds = [RANDOMNESS_ORACLES_SEED], bump)]
+    pub randomness_oracles: Option<Box<Account<'info, RandomnessOracles>>>,
+    /// CHECK: The foal's `FoalRecord`, created here if the pairing succeeds
+    #[account(
+        mut,
+        seeds = [FOAL_RECORD_SEED, player.pending_action.foal_id().to_le_bytes().as_ref()],
+        bump
+    )]
+    pub foal_record: AccountInfo<'info>,
+    pub system_program: Program<'info, System>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+#[event]
+pub struct PairBred {
+    pub player: Pubkey,
+    pub sire: Horse,
+    pub dam: Horse,
+    pub foal: Option<Horse>,
+    pub look_id: Option<u16>,
+    pub reveal_slot: u64,
+}
+
+pub fn breed_pair_settle(ctx: Context<BreedPairSettle>, secret: [u8; 32]) -> Result<()> {
+    let clock: Clock = Clock::get()?;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    let secret = revealed_secret(&player.entropy_commitment, &secret)?;
+    let reveal = reveal_randomness(
+        player,
+        &ctx.accounts.slot_hashes,
+        &ctx.accounts.randomness_oracle,
+        &ctx.accounts.randomness_oracles,
+        clock.slot,
+    )?;
+
+    settle_and_mint_rewards(
+        player,
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        clock.slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    let (sire_index, dam_index, success_bps, foal_id) = if let PendingRandomAction::PairBreeding {
+        sire_index,
+        dam_index,
+        success_bps,
+        foal_id,
+    } = player.pending_action
+    {
+        (sire_index, dam_index, success_bps, foal_id)
+    } else {
+        return Err(HorseGameError::NoBreedingPending.into());
+    };
+
+    // Horses cannot move while an action is pending, so the parents are unchanged
+    let sire = player.horses[sire_index as usize];
+    let dam = player.horses[dam_index as usize];
+
+    let mut rng = RandomStream::with_secret(&reveal.value, &secret, &player.key());
+    let (foal, look_id) = if rng.chance_bps(success_bps as u32) {
+        let (foal, look_id) = breed_foal(&sire, &dam, foal_id, &mut rng);
+        require!(
+            (player.horse_count as usize) < MAX_HORSES_PER_PLAYER as usize,
+            HorseGameError::StableCapacityExceeded
+        );
+        player.add_horse(foal)?;
+
+        let foal_id_bytes = foal.id.to_le_bytes();
+        let record_seeds: &[&[u8]] = &[
+            FOAL_RECORD_SEED,
+            &foal_id_bytes,
+            &[ctx.bumps.foal_record],
+        ];
+        create_pda_account(
+            &ctx.accounts.player_wallet.to_account_info(),
+            &ctx.accounts.foal_record,
+            &ctx.accounts.system_program.to_account_info(),
+            8 + 6 + 2 + 6 + 6 + 32 + 8,
+            record_seeds,
+        )?;
+        let record = FoalRecord {
+            foal,
+            look_id,
+            sire,
+            dam,
+            breeder: player.owner,
+            bred_slot: clock.slot,
+        };
+        record.try_serialize(&mut &mut ctx.accounts.foal_record.try_borrow_mut_data()?[..])?;
+
+        player.successful_breeding = player.successful_breeding.saturating_add(1);
+        gs.total_successful_breeding = gs.total_successful_breeding.saturating_add(1);
+        (Some(foal), Some(look_id))
+    } else {
+        (None, None)
+    };
+
+    player.pending_action = PendingRandomAction::None;
+    player.commit_slot = 0;
+    player.entropy_commitment = [0; 32];
+    player.total_horses_bred = player.total_horses_bred.saturating_add(2);
+
+    emit!(PairBred {
+        player: player.key(),
+        sire,
+        dam,
+        foal,
+        look_id,
+        reveal_slot: reveal.slot,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// GAMBLE (commit phase)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct GambleCommit<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::GambleAlreadyPending,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = player_wallet,
+        token::mint = token_mint,
+        token::authority = global_state,
+        token::token_program = token_program,
+        seeds = [GAMBLE_BANKROLL_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub gamble_bankroll: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    /// CHECK: This is the fees recipient wallet from global_state
+    #[account(
+        mut,
+        constraint = fees_wallet.key() == global_state.fees_wallet @ HorseGameError::Unauthorized
+    )]
+    pub fees_wallet: AccountInfo<'info>,
+    #[account(
+        init_if_needed,
+        payer = player_wallet,
+        space = 8 + 8,
+        seeds = [SOL_REWARDS_WALLET_SEED],
+        bump
+    )]
+    pub sol_rewards_wallet: Box<Account<'info, SolRewardsWallet>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+#[event]
+pub struct GambleCommitted {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub commit_slot: u64,
+}
+
+pub fn gamble_commit(ctx: Context<GambleCommit>, amount: u64) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
+    require!(amount > 0, HorseGameError::InvalidGambleAmount);
+    validate_gamble_odds(
+        gs.gamble_win_chance_bps as u64,
+        gs.gamble_house_edge_bps as u64,
+    )?;
+    require!(
+        ctx.accounts.player_token_account.amount >= amount,
+        HorseGameError::InsufficientTokens
+    );
+
+    // The bankroll, including this stake, must cover this win on top of every
+    // win already reserved by pending gambles
+    let max_payout = calculate_gamble_payout(
+        amount,
+        gs.gamble_win_chance_bps,
+        gs.gamble_house_edge_bps,
+    );
+    require!(
+        ctx.accounts.gamble_bankroll.amount.saturating_add(amount)
+            >= gs.gamble_liability.saturating_add(max_payout),
+        HorseGameError::InsufficientTokens
+    );
+
+    settle_and_mint_rewards(
+        player,
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    // SOL fee, with the stakers' share routed to the SOL rewards wallet
+    let gamble_fee = gs.gamble_fee_lamports;
+    let (stakers_share, fees_share) = split_sol_fee(gs, gamble_fee);
+
+    if stakers_share > 0 {
+        deposit_sol_rewards_to_stakers(
+            gs,
+            &ctx.accounts.player_wallet.to_account_info(),
+            &ctx.accounts.sol_rewards_wallet.to_account_info(),
+            &ctx.accounts.system_program.to_account_info(),
+            stakers_share,
+        )?;
+    }
+
+    if fees_share > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.player_wallet.to_account_info(),
+                    to: ctx.accounts.fees_wallet.to_account_info(),
+                },
+            ),
+            fees_share,
+        )?;
+    }
+
+    // Stake goes into the bankroll until the gamble is settled
+    token_interface::transfer_checked(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::TransferChecked {
+                from: ctx.accounts.player_token_account.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.gamble_bankroll.to_account_info(),
+                authority: ctx.accounts.player_wallet.to_account_info(),
+            },
+        ),
+        amount,
+        ctx.accounts.token_mint.decimals,
+    )?;
+
+    player.pending_action = PendingRandomAction::Gamble {
+        amount,
+        payout: max_payout,
+        win_chance_bps: gs.gamble_win_chance_bps,
+    };
+    player.commit_slot = slot;
+    gs.gamble_liability = gs.gamble_liability.saturating_add(max_payout);
+    player.total_sol_spent = player.total_sol_spent.saturating_add(gamble_fee);
+
+    emit!(GambleCommitted {
+        player: player.key(),
+        amount,
+        commit_slot: slot,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// GAMBLE (settle phase)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct GambleSettle<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = matches!(player.pending_action, PendingRandomAction::Gamble { .. }) @ HorseGameError::NoGamblePending,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [GAMBLE_BANKROLL_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub gamble_bankroll: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// CHECK: Checked manually
+    pub slot_hashes: AccountInfo<'info>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+#[event]
+pub struct GambleSettled {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub won: bool,
+    pub payout: u64,
+    pub roll_bps: u16,
+    pub win_chance_bps: u16,
+}
+
+/// Gambles always reveal from SlotHashes, whatever `randomness_source` is:
+/// `cancel_pending_action` refunds or forfeits by whether the reveal slot was
+/// skipped or has expired, which only the sysvar can tell
+pub fn gamble_settle(ctx: Context<GambleSettle>) -> Result<()> {
+    let clock: Clock = Clock::get()?;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    require!(
+        clock.slot >= player.commit_slot + MIN_RANDOMNESS_DELAY_SLOTS,
+        HorseGameError::RandomnessNotResolved
+    );
+    let reveal_slot = player.commit_slot + MIN_RANDOMNESS_DELAY_SLOTS;
+
+    let random_value = match lookup_slot_hash(&ctx.accounts.slot_hashes, reveal_slot)? {
+        SlotHashLookup::Found(hash) => hash,
+        _ => return err!(HorseGameError::SlotNotFound),
+    };
+
+    let (amount, reserved_payout, win_chance_bps) = match player.pending_action {
+        PendingRandomAction::Gamble {
+            amount,
+            payout,
+            win_chance_bps,
+        } => (amount, payout, win_chance_bps),
+        _ => return Err(HorseGameError::NoGamblePending.into()),
+    };
+    gs.gamble_liability = gs.gamble_liability.saturating_sub(reserved_payout);
+
+    settle_and_mint_rewards(
+        player,
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        clock.slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    let mut random_bytes: [u8; 4] = [0; 4];
+    random_bytes.copy_from_slice(&random_value[0..4]);
+    let random_u32 = u32::from_le_bytes(random_bytes);
+    let roll_bps = (random_u32 as u64 * BPS_DENOMINATOR / (u32::MAX as u64 + 1)) as u16;
+
+    let won = roll_bps < win_chance_bps;
+    let mut payout = 0u64;
+
+    if won {
+        payout = reserved_payout;
+
+        let bankroll_balance = ctx.accounts.gamble_bankroll.amount;
+        if payout > bankroll_balance {
+            payout = bankroll_balance;
+            msg!("⚠️ Gamble payout capped to bankroll balance: {}", bankroll_balance);
+        }
+
+        if payout > 0 {
+            let seeds = &[
+                GLOBAL_STATE_SEED,
+                &[ctx.bumps.global_state],
+            ];
+            let signer = &[&seeds[..]];
+
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: ctx.accounts.gamble_bankroll.to_account_info(),
+                        mint: ctx.accounts.token_mint.to_account_info(),
+                        to: ctx.accounts.player_token_account.to_account_info(),
+                        authority: gs.to_account_info(),
+                    },
+                    signer,
+                ),
+                payout,
+                ctx.accounts.token_mint.decimals,
+            )?;
+        }
+
+        player.total_gamble_wins = player.total_gamble_wins.saturating_add(1);
+        gs.total_global_gamble_wins = gs.total_global_gamble_wins.saturating_add(1);
+    }
+
+    player.pending_action = PendingRandomAction::None;
+    player.commit_slot = 0;
+    player.total_gambles = player.total_gambles.saturating_add(1);
+    gs.total_global_gambles = gs.total_global_gambles.saturating_add(1);
+
+    emit!(GambleSettled {
+        player: player.key(),
+        amount,
+        won,
+        payout,
+        roll_bps,
+        win_chance_bps,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// CANCEL PENDING ACTION
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct CancelPendingAction<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action != PendingRandomAction::None @ HorseGameError::NoPendingAction,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Required to refund a pending gamble
+    #[account(
+        mut,
+        seeds = [GAMBLE_BANKROLL_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub gamble_bankroll: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+    /// CHECK: Checked manually; required to cancel a pending gamble
+    pub slot_hashes: Option<AccountInfo<'info>>,
+    /// Required to cancel a pending pack that is not the built-in one
+    #[account(
+        mut,
+        seeds = [PACK_TYPE_SEED, player.pending_action.pack_type().to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pack: Option<Box<Account<'info, PackType>>>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+#[event]
+pub struct GambleCancelled {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub refunded: u64,
+}
+
+pub fn cancel_pending_action(ctx: Context<CancelPendingAction>) -> Result<()> {
+    let player = &mut ctx.accounts.player;
+    let clock = Clock::get()?;
+
+    settle_and_mint_rewards(
+        player,
+        &mut ctx.accounts.global_state,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        clock.slot,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    require!(
+        clock.slot > player.commit_slot + CANCEL_TIMEOUT_SLOTS,
+        HorseGameError::CancelTimeoutNotExpired
+    );
+
+    if let PendingRandomAction::Breeding {
+        horse_indices,
+        horse_count,
+    } = player.pending_action.clone()
+    {
+        let mut indices_to_remove: Vec<u8> = horse_indices[0..horse_count as usize].to_vec();
+        indices_to_remove.sort_by(|a, b| b.cmp(a));
+        player.batch_remove_horses(&indices_to_remove)?;
+    }
+
+    let pending_pack = matches!(
+        player.pending_action,
+        PendingRandomAction::PackOpening { .. }
+    );
+    if pending_pack && player.pending_action.pack_type() != DEFAULT_PACK_TYPE_ID {
+        // The pack is forfeited, but it no longer counts against a supply cap
+        let pack = ctx
+            .accounts
+            .pack
+            .as_deref_mut()
+            .ok_or(HorseGameError::PackTypeMissing)?;
+        pack.opened = pack.opened.saturating_sub(1);
+    }
+
+    if let PendingRandomAction::Gamble {
+        amount,
+        payout: reserved_payout,
+        ..
+    } = player.pending_action
+    {
+        let slot_hashes = ctx
+            .accounts
+            .slot_hashes
+            .as_ref()
+            .ok_or(HorseGameError::InvalidSlotHashes)?;
+        let bankroll = ctx
+            .accounts
+            .gamble_bankroll
+            .as_ref()
+            .ok_or(HorseGameError::GambleBankrollMissing)?;
+        let reveal_slot = player.commit_slot + MIN_RANDOMNESS_DELAY_SLOTS;
+
+        let refund = gamble_cancel_refund(
+            lookup_slot_hash(slot_hashes, reveal_slot)?,
+            amount,
+            bankroll.amount,
+        )?;
+        let gs = &mut ctx.accounts.global_state;
+        gs.gamble_liability = gs.gamble_liability.saturating_sub(reserved_payout);
+
+        if refund > 0 {
+            let seeds = &[
+                GLOBAL_STATE_SEED,
+                &[ctx.bumps.global_state],
+            ];
+            let signer = &[&seeds[..]];
+
+            token_interface::transfer_checked(
+                CpiContext::new_with_signer(
+                    ctx.accounts.token_program.to_account_info(),
+                    token_interface::TransferChecked {
+                        from: bankroll.to_account_info(),
+                        mint: ctx.accounts.token_mint.to_account_info(),
+                        to: ctx.accounts.player_token_account.to_account_info(),
+                        authority: ctx.accounts.global_state.to_account_info(),
+                    },
+                    signer,
+                ),
+                refund,
+                ctx.accounts.token_mint.decimals,
+            )?;
+        }
+
+        emit!(GambleCancelled {
+            player: player.key(),
+            amount,
+            refunded: refund,
+        });
+    }
+
+    player.pending_action = PendingRandomAction::None;
+    player.commit_slot = 0;
+    player.entropy_commitment = [0; 32];
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// STAKE TOKENS
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct StakeTokens<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        init_if_needed,
+        payer = player_wallet,
+        token::mint = token_mint,
+        token::authority = global_state,
+        token::token_program = token_program,
+        seeds = [STAKING_VAULT_SEED, token_mint.key().as_ref()],
+        bump
+    )]
+    pub staking_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        init_if_needed,
+        payer = player_wallet,
+        space = 8 + 8,
+        seeds = [SOL_REWARDS_WALLET_SEED],
+        bump
+    )]
+    pub sol_rewards_wallet: Box<Account<'info, SolRewardsWallet>>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct TokensStaked {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub staked_tokens: u64,
+    pub total_staked_tokens: u64,
+    pub unlock_slot: u64,
+}
+
+pub fn stake_tokens(ctx: Context<StakeTokens>, amount: u64) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
+    require!(amount > 0, HorseGameError::InvalidStakeAmount);
+    require!(
+        ctx.accounts.player_token_account.amount >= amount,
+        HorseGameError::InsufficientTokens
+    );
+
+    settle_staking_rewards(
+        player,
+        gs,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+    settle_sol_rewards(
+        player,
+        gs,
+        &mut ctx.accounts.sol_rewards_wallet,
+        &ctx.accounts.player_wallet.to_account_info(),
+    )?;
+
+    token_interface::transfer_checked(
+        CpiContext::new(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::TransferChecked {
+                from: ctx.accounts.player_token_account.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.staking_vault.to_account_info(),
+                authority: ctx.accounts.player_wallet.to_account_info(),
+            },
+        ),
+        amount,
+        ctx.accounts.token_mint.decimals,
+    )?;
+
+    player.staked_tokens = player
+        .staked_tokens
+        .checked_add(amount)
+        .ok_or(HorseGameError::ArithmeticOverflow)?;
+    player.last_stake_slot = slot;
+    gs.total_staked_tokens = gs
+        .total_staked_tokens
+        .checked_add(amount)
+        .ok_or(HorseGameError::ArithmeticOverflow)?;
+
+    emit!(TokensStaked {
+        player: player.key(),
+        amount,
+        staked_tokens: player.staked_tokens,
+        total_staked_tokens: gs.total_staked_tokens,
+        unlock_slot: slot.saturating_add(gs.staking_lockup_slots),
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// UNSTAKE TOKENS
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct UnstakeTokens<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        seeds = [STAKING_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub staking_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(
+        mut,
+        seeds = [SOL_REWARDS_WALLET_SEED],
+        bump,
+    )]
+    pub sol_rewards_wallet: Box<Account<'info, SolRewardsWallet>>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event]
+pub struct TokensUnstaked {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub staked_tokens: u64,
+    pub total_staked_tokens: u64,
+}
+
+pub fn unstake_tokens(ctx: Context<UnstakeTokens>, amount: u64) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+
+    require!(amount > 0, HorseGameError::InvalidStakeAmount);
+    require!(
+        player.staked_tokens >= amount,
+        HorseGameError::InsufficientStakedTokens
+    );
+    require!(
+        slot >= player.last_stake_slot.saturating_add(gs.staking_lockup_slots),
+        HorseGameError::StakingLockupNotExpired
+    );
+
+    settle_staking_rewards(
+        player,
+        gs,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+    settle_sol_rewards(
+        player,
+        gs,
+        &mut ctx.accounts.sol_rewards_wallet,
+        &ctx.accounts.player_wallet.to_account_info(),
+    )?;
+
+    player.staked_tokens -= amount;
+    gs.total_staked_tokens = gs.total_staked_tokens.saturating_sub(amount);
+
+    let seeds = &[
+        GLOBAL_STATE_SEED,
+        &[ctx.bumps.global_state],
+    ];
+    let signer = &[&seeds[..]];
+
+    token_interface::transfer_checked(
+        CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            token_interface::TransferChecked {
+                from: ctx.accounts.staking_vault.to_account_info(),
+                mint: ctx.accounts.token_mint.to_account_info(),
+                to: ctx.accounts.player_token_account.to_account_info(),
+                authority: gs.to_account_info(),
+            },
+            signer,
+        ),
+        amount,
+        ctx.accounts.token_mint.decimals,
+    )?;
+
+    emit!(TokensUnstaked {
+        player: player.key(),
+        amount,
+        staked_tokens: player.staked_tokens,
+        total_staked_tokens: gs.total_staked_tokens,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// CLAIM STAKING REWARDS
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct ClaimStakingRewards<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[event]
+pub struct StakingRewardsClaimed {
+    pub player: Pubkey,
+    pub amount: u64,
+    pub claimed_token_rewards: u64,
+}
+
+pub fn claim_staking_rewards(ctx: Context<ClaimStakingRewards>) -> Result<()> {
+    let player = &mut ctx.accounts.player;
+
+    let amount = settle_staking_rewards(
+        player,
+        &mut ctx.accounts.global_state,
+        &ctx.accounts.player_token_account.to_account_info(),
+        &ctx.accounts.token_mint,
+        &ctx.accounts.rewards_vault,
+        &ctx.accounts.token_program.to_account_info(),
+        ctx.bumps.global_state,
+    )?;
+
+    emit!(StakingRewardsClaimed {
+        player: player.key(),
+        amount,
+        claimed_token_rewards: player.claimed_token_rewards,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// DEPOSIT SOL REWARDS (permissionless treasury top-up)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct DepositSolRewards<'info> {
+    #[account(mut)]
+    pub depositor: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = depositor,
+        space = 8 + 8,
+        seeds = [SOL_REWARDS_WALLET_SEED],
+        bump
+    )]
+    pub sol_rewards_wallet: Box<Account<'info, SolRewardsWallet>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct SolRewardsDeposited {
+    pub depositor: Pubkey,
+    pub amount: u64,
+    pub total_sol_deposited: u64,
+    pub acc_sol_rewards_per_token: u128,
+}
+
+pub fn deposit_sol_rewards(ctx: Context<DepositSolRewards>, amount: u64) -> Result<()> {
+    let gs = &mut ctx.accounts.global_state;
+
+    require!(amount > 0, HorseGameError::InvalidDepositAmount);
+
+    deposit_sol_rewards_to_stakers(
+        gs,
+        &ctx.accounts.depositor.to_account_info(),
+        &ctx.accounts.sol_rewards_wallet.to_account_info(),
+        &ctx.accounts.system_program.to_account_info(),
+        amount,
+    )?;
+
+    emit!(SolRewardsDeposited {
+        depositor: ctx.accounts.depositor.key(),
+        amount,
+        total_sol_deposited: gs.total_sol_deposited,
+        acc_sol_rewards_per_token: gs.acc_sol_rewards_per_token,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// CLAIM SOL REWARDS
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct ClaimSolRewards<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [SOL_REWARDS_WALLET_SEED],
+        bump,
+    )]
+    pub sol_rewards_wallet: Box<Account<'info, SolRewardsWallet>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[event]
+pub struct SolRewardsClaimed {
+    pub player: Pubkey,
+    pub amount: u64,
+}
+
+pub fn claim_sol_rewards(ctx: Context<ClaimSolRewards>) -> Result<()> {
+    let player = &mut ctx.accounts.player;
+
+    let amount = settle_sol_rewards(
+        player,
+        &ctx.accounts.global_state,
+        &mut ctx.accounts.sol_rewards_wallet,
+        &ctx.accounts.player_wallet.to_account_info(),
+    )?;
+
+    emit!(SolRewardsClaimed {
+        player: player.key(),
+        amount,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// PROPOSE AUTHORITY (admin) – step 1 of the authority handoff
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + 32 + 32 + 8,
+        seeds = [PENDING_AUTHORITY_SEED],
+        bump
+    )]
+    pub pending_authority: Account<'info, PendingAuthority>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub current_authority: Pubkey,
+    pub proposed_authority: Pubkey,
+    pub slot: u64,
+}
+
+/// Proposing again replaces any earlier proposal
+pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+    require!(
+        new_authority != Pubkey::default(),
+        HorseGameError::InvalidAuthority
+    );
+
+    let slot = Clock::get()?.slot;
+    let pending = &mut ctx.accounts.pending_authority;
+    pending.proposed_authority = new_authority;
+    pending.proposed_by = ctx.accounts.authority.key();
+    pending.proposed_slot = slot;
+
+    emit!(AuthorityProposed {
+        current_authority: ctx.accounts.authority.key(),
+        proposed_authority: new_authority,
+        slot,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// ACCEPT AUTHORITY – step 2, signed by the proposed key
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
+    #[account(mut)]
+    pub new_authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        close = new_authority,
+        seeds = [PENDING_AUTHORITY_SEED],
+        bump,
+        constraint = pending_authority.proposed_authority == new_authority.key() @ HorseGameError::Unauthorized,
+        // A proposal made by a since-replaced authority is stale
+        constraint = pending_authority.proposed_by == global_state.authority @ HorseGameError::Unauthorized,
+    )]
+    pub pending_authority: Account<'info, PendingAuthority>,
+}
+
+#[event]
+pub struct AuthorityTransferred {
+    pub previous_authority: Pubkey,
+    pub new_authority: Pubkey,
+    pub slot: u64,
+}
+
+pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+    let gs = &mut ctx.accounts.global_state;
+    let previous_authority = gs.authority;
+    gs.authority = ctx.accounts.new_authority.key();
+
+    emit!(AuthorityTransferred {
+        previous_authority,
+        new_authority: gs.authority,
+        slot: Clock::get()?.slot,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// SET EMISSION SCHEDULE (admin)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct SetEmissionSchedule<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + 1 + 1 + (MAX_EMISSION_SEGMENTS * 16) + 8 + 8 + 8,
+        seeds = [EMISSION_SCHEDULE_SEED],
+        bump
+    )]
+    pub emission_schedule: Box<Account<'info, EmissionSchedule>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct EmissionScheduleUpdated {
+    pub mode: u8,
+    pub segments: Vec<EmissionSegment>,
+    pub halving_initial_rate: u64,
+    pub halving_interval_slots: u64,
+    pub slot: u64,
+}
+
+pub fn set_emission_schedule(
+    ctx: Context<SetEmissionSchedule>,
+    mode: u8,
+    segments: Vec<EmissionSegment>,
+    halving_initial_rate: u64,
+    halving_interval_slots: u64,
+) -> Result<()> {
+    validate_emission_schedule(mode, &segments, halving_initial_rate, halving_interval_slots)?;
+
+    let slot = Clock::get()?.slot;
+    let gs = &mut ctx.accounts.global_state;
+    let schedule = &mut ctx.accounts.emission_schedule;
+
+    // Settle the elapsed window under the curve that was in effect until now
+    let previous = if gs.emission_schedule_enabled {
+        Some(&***schedule)
+    } else {
+        None
+    };
+    accrue_pool_rewards(gs, previous, slot);
+
+    schedule.mode = mode;
+    schedule.segments = [EmissionSegment::default(); MAX_EMISSION_SEGMENTS];
+    if mode == EMISSION_MODE_SEGMENTS {
+        schedule.segment_count = segments.len() as u8;
+        schedule.segments[..segments.len()].copy_from_slice(&segments);
+        schedule.halving_initial_rate = 0;
+        schedule.halving_interval_slots = 0;
+    } else {
+        schedule.segment_count = 0;
+        schedule.halving_initial_rate = halving_initial_rate;
+        schedule.halving_interval_slots = halving_interval_slots;
+    }
+    schedule.updated_slot = slot;
+    gs.emission_schedule_enabled = true;
+
+    emit!(EmissionScheduleUpdated {
+        mode,
+        segments: schedule.segments[..schedule.segment_count as usize].to_vec(),
+        halving_initial_rate: schedule.halving_initial_rate,
+        halving_interval_slots: schedule.halving_interval_slots,
+        slot,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// CONFIGURE SPEED HISTORY (admin)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct ConfigureSpeedHistory<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + 8 + 8 + 2 + 4 + (SPEED_HISTORY_CAPACITY * 24),
+        seeds = [SPEED_HISTORY_SEED],
+        bump
+    )]
+    pub speed_history: Box<Account<'info, SpeedHistory>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct SpeedHistoryConfigured {
+    pub interval_slots: u64,
+    pub enabled: bool,
+}
+
+/// Sets the checkpoint interval; an interval of 0 stops recording
+pub fn configure_speed_history(
+    ctx: Context<ConfigureSpeedHistory>,
+    interval_slots: u64,
+) -> Result<()> {
+    ctx.accounts.speed_history.interval_slots = interval_slots;
+    ctx.accounts.global_state.speed_history_enabled = interval_slots > 0;
+
+    emit!(SpeedHistoryConfigured {
+        interval_slots,
+        enabled: interval_slots > 0,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// SET STABLE TIERS (admin)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct SetStableTiers<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + 1 + (MAX_STABLE_TIERS * 17),
+        seeds = [STABLE_TIERS_SEED],
+        bump
+    )]
+    pub stable_tiers: Box<Account<'info, StableTierConfig>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct StableTiersUpdated {
+    pub tiers: Vec<StableTier>,
+}
+
+/// Replaces the stable tier table. Applies at once rather than through the
+/// `update_config` timelock: existing levels can only grow, and an upgrade pays
+/// the cost in force when it lands
+pub fn set_stable_tiers(ctx: Context<SetStableTiers>, tiers: Vec<StableTier>) -> Result<()> {
+    let gs = &mut ctx.accounts.global_state;
+    let config = &mut ctx.accounts.stable_tiers;
+
+    let current: Vec<StableTier> = if gs.stable_tiers_enabled {
+        config.tiers[..config.tier_count as usize].to_vec()
+    } else {
+        (0..STABLE_CONFIGS.len() as u8)
+            .filter_map(default_stable_tier)
+            .collect()
+    };
+    validate_stable_tiers(&tiers, &current)?;
+
+    config.tier_count = tiers.len() as u8;
+    config.tiers = [StableTier::default(); MAX_STABLE_TIERS];
+    config.tiers[..tiers.len()].copy_from_slice(&tiers);
+    gs.stable_tiers_enabled = true;
+
+    emit!(StableTiersUpdated { tiers });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// HORSE CATALOG (admin)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct InitHorseCatalog<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 2 * (HORSE_GRADE_COUNT + 1) + 2 * HORSE_GRADE_COUNT + 1 + 4 + (MAX_CATALOG_HORSES * (9 + CATALOG_NAME_LEN)),
+        seeds = [HORSE_CATALOG_SEED],
+        bump
+    )]
+    pub horse_catalog: Box<Account<'info, HorseCatalog>>,
+    pub system_program: Program<'info, System>,
+}
+
+/// Creates the catalog seeded with HORSE_DATA, which lists each grade in the
+/// id order the catalog keeps, so draws are unchanged, and switches packs and
+/// breeding over to it
+pub fn init_horse_catalog(ctx: Context<InitHorseCatalog>) -> Result<()> {
+    let catalog = &mut ctx.accounts.horse_catalog;
+    catalog.entries = Vec::with_capacity(HORSE_DATA.len());
+
+    for grade in 0..HORSE_GRADE_COUNT {
+        catalog.grade_start[grade] = catalog.entries.len() as u16;
+        for &(id, horse_grade, speed, stamina_cost) in HORSE_DATA.iter() {
+            if horse_grade as usize == grade {
+                catalog.entries.push(CatalogHorse {
+                    id,
+                    speed,
+                    stamina_cost,
+                    flags: 0,
+                    drop_enabled: true,
+                    supply_left: 0,
+                    name: [0u8; CATALOG_NAME_LEN],
+                });
+            }
+        }
+        catalog.droppable_count[grade] =
+            catalog.entries.len() as u16 - catalog.grade_start[grade];
+    }
+    catalog.grade_start[HORSE_GRADE_COUNT] = catalog.entries.len() as u16;
+    catalog.season_open = false;
+
+    ctx.accounts.global_state.horse_catalog_enabled = true;
+
+    emit!(CatalogHorsesAdded {
+        horse_ids: catalog.entries.iter().map(|horse| horse.id).collect(),
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct EditHorseCatalog<'info> {
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        seeds = [HORSE_CATALOG_SEED],
+        bump
+    )]
+    pub horse_catalog: Box<Account<'info, HorseCatalog>>,
+}
+
+#[event]
+pub struct CatalogHorsesAdded {
+    pub horse_ids: Vec<u16>,
+}
+
+#[event]
+pub struct CatalogHorseUpdated {
+    pub horse_id: u16,
+    pub flags: u8,
+    pub droppable: bool,
+    pub supply_left: u16,
+}
+
+#[event]
+pub struct CatalogSeasonSet {
+    pub open: bool,
+}
+
+pub fn add_catalog_horses(
+    ctx: Context<EditHorseCatalog>,
+    horses: Vec<NewCatalogHorse>,
+) -> Result<()> {
+    let catalog = &mut ctx.accounts.horse_catalog;
+    catalog.entries.reserve_exact(horses.len());
+
+    for horse in &horses {
+        require!(
+            horse.id != 0
+                && horse.id < FOAL_ID_START
+                && horse.grade <= GRADE_SS
+                && horse.speed > 0
+                && horse.stamina_cost > 0,
+            HorseGameError::InvalidCatalogHorse
+        );
+        require!(
+            catalog.find(horse.id).is_none(),
+            HorseGameError::InvalidCatalogHorse
+        );
+
+        catalog.insert(
+            horse.grade,
+            CatalogHorse {
+                id: horse.id,
+                speed: horse.speed,
+                stamina_cost: horse.stamina_cost,
+                flags: horse.flags,
+                drop_enabled: true,
+                supply_left: horse.supply,
+                name: horse.name,
+            },
+        )?;
+    }
+
+    emit!(CatalogHorsesAdded {
+        horse_ids: horses.iter().map(|horse| horse.id).collect(),
+    });
+
+    Ok(())
+}
+
+/// Edits a horse's name, flags and limited supply; non-droppable horses stay
+/// in the catalog (owned copies are unaffected) but packs and breeding no
+/// longer award them
+pub fn update_catalog_horse(
+    ctx: Context<EditHorseCatalog>,
+    horse_id: u16,
+    name: [u8; CATALOG_NAME_LEN],
+    flags: u8,
+    droppable: bool,
+    supply_left: u16,
+) -> Result<()> {
+    let catalog = &mut ctx.accounts.horse_catalog;
+    let (grade, index) = catalog
+        .find(horse_id)
+        .ok_or(HorseGameError::CatalogHorseNotFound)?;
+
+    let horse = &mut catalog.entries[index];
+    horse.name = name;
+    horse.flags = flags;
+    horse.drop_enabled = droppable;
+    horse.supply_left = supply_left;
+    catalog.partition(grade);
+
+    emit!(CatalogHorseUpdated {
+        horse_id,
+        flags,
+        droppable,
+        supply_left,
+    });
+
+    Ok(())
+}
+
+/// Opens or closes the season; seasonal horses only drop while it is open
+pub fn set_catalog_season(ctx: Context<EditHorseCatalog>, open: bool) -> Result<()> {
+    let catalog = &mut ctx.accounts.horse_catalog;
+    catalog.season_open = open;
+    for grade in 0..HORSE_GRADE_COUNT as u8 {
+        catalog.partition(grade);
+    }
+
+    emit!(CatalogSeasonSet { open });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// SET PACK TYPE (admin)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(pack_type: u16)]
+pub struct SetPackType<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + 2 + 8 + 9 + 1 + (2 * HORSE_GRADE_COUNT) + 9 + 8 + 1,
+        seeds = [PACK_TYPE_SEED, pack_type.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub pack: Box<Account<'info, PackType>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct PackTypeUpdated {
+    pub pack_type: u16,
+    pub price_microtokens: u64,
+    pub sol_price_lamports: Option<u64>,
+    pub horse_count: u8,
+    pub grade_weights: [u16; HORSE_GRADE_COUNT],
+    pub supply_cap: Option<u64>,
+    pub active: bool,
+}
+
+/// Creates or edits a pack type; the sold count carries over across edits.
+/// Applies at once rather than through the `update_config` timelock: pending
+/// openings settle with the count and odds snapshotted at commit
+pub fn set_pack_type(ctx: Context<SetPackType>, pack_type: u16, config: PackTypeConfig) -> Result<()> {
+    require!(
+        pack_type != DEFAULT_PACK_TYPE_ID,
+        HorseGameError::InvalidPackType
+    );
+
+    let pack = &mut ctx.accounts.pack;
+    validate_pack_type_config(&config, pack.opened)?;
+
+    pack.id = pack_type;
+    pack.price_microtokens = config.price_microtokens;
+    pack.sol_price_lamports = config.sol_price_lamports;
+    pack.horse_count = config.horse_count;
+    pack.grade_weights = config.grade_weights;
+    pack.supply_cap = config.supply_cap;
+    pack.active = config.active;
+
+    emit!(PackTypeUpdated {
+        pack_type,
+        price_microtokens: config.price_microtokens,
+        sol_price_lamports: config.sol_price_lamports,
+        horse_count: config.horse_count,
+        grade_weights: config.grade_weights,
+        supply_cap: config.supply_cap,
+        active: config.active,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// SET BREEDING CONFIG (admin)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct SetBreedingConfig<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + (8 * HORSE_GRADE_COUNT) + (2 * HORSE_GRADE_COUNT) + 2 + 8,
+        seeds = [BREEDING_CONFIG_SEED],
+        bump
+    )]
+    pub breeding_config: Box<Account<'info, BreedingConfig>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct BreedingConfigUpdated {
+    pub pair_fee_microtokens: [u64; HORSE_GRADE_COUNT],
+    pub pair_success_bps: [u16; HORSE_GRADE_COUNT],
+    pub pair_cooldown_slots: u64,
+}
+
+/// Sets pair breeding fees and odds per grade; zero odds disable a grade.
+/// Applies at once rather than through the `update_config` timelock: the fee is
+/// charged at commit and pending pairings keep the odds snapshotted there
+pub fn set_breeding_config(
+    ctx: Context<SetBreedingConfig>,
+    pair_fee_microtokens: [u64; HORSE_GRADE_COUNT],
+    pair_success_bps: [u16; HORSE_GRADE_COUNT],
+    pair_cooldown_slots: u64,
+) -> Result<()> {
+    require!(
+        pair_success_bps.iter().all(|&bps| bps <= 10_000),
+        HorseGameError::InvalidBreedingConfig
+    );
+    require!(pair_cooldown_slots > 0, HorseGameError::InvalidBreedingConfig);
+
+    let config = &mut ctx.accounts.breeding_config;
+    config.pair_fee_microtokens = pair_fee_microtokens;
+    config.pair_success_bps = pair_success_bps;
+    config.pair_cooldown_slots = pair_cooldown_slots;
+
+    emit!(BreedingConfigUpdated {
+        pair_fee_microtokens,
+        pair_success_bps,
+        pair_cooldown_slots,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// SET RANDOMNESS SOURCE (admin)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct SetRandomnessSource<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + 4 + 32 * MAX_RANDOMNESS_ORACLES,
+        seeds = [RANDOMNESS_ORACLES_SEED],
+        bump
+    )]
+    pub randomness_oracles: Box<Account<'info, RandomnessOracles>>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct RandomnessSourceUpdated {
+    pub source: u8,
+    pub oracle_program: Option<Pubkey>, // Oracle new commits use, when `source` is the oracle
+}
+
+/// Selects where new pack and breeding commits take their randomness from;
+/// actions already committed settle with the source and oracle they were
+/// committed under. `oracle_program` is ignored for SlotHashes
+pub fn set_randomness_source(
+    ctx: Context<SetRandomnessSource>,
+    source: u8,
+    oracle_program: Pubkey,
+) -> Result<()> {
+    let gs = &mut ctx.accounts.global_state;
+    let oracles = &mut ctx.accounts.randomness_oracles;
+
+    let oracle_program = match source {
+        RANDOMNESS_SOURCE_SLOT_HASHES => None,
+        RANDOMNESS_SOURCE_ORACLE => {
+            require!(
+                oracle_program != Pubkey::default(),
+                HorseGameError::InvalidRandomnessOracle
+            );
+            let index = match oracles.programs.iter().position(|p| *p == oracle_program) {
+                Some(index) => index,
+                None => {
+                    require!(
+                        oracles.programs.len() < MAX_RANDOMNESS_ORACLES,
+                        HorseGameError::RandomnessOraclesFull
+                    );
+                    oracles.programs.push(oracle_program);
+                    oracles.programs.len() - 1
+                }
+            };
+            gs.randomness_oracle_index = index as u8;
+            Some(oracle_program)
+        }
+        _ => return err!(HorseGameError::InvalidRandomnessSource),
+    };
+    gs.randomness_source = source;
+
+    emit!(RandomnessSourceUpdated {
+        source,
+        oracle_program,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// CREATE RACE EVENT (admin)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(race_id: u64)]
+pub struct CreateRace<'info> {
+    #[account(mut)]
+    pub authority: Signer<'info>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        bump,
+        has_one = authority @ HorseGameError::Unauthorized
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        payer = authority,
+        space = 8 + 8 + 8 + 8 + 1 + (MAX_RACE_ENTRANTS as usize * 37) + 1 + 1 + RACE_PODIUM_SIZE + RACE_PODIUM_SIZE + 1,
+        seeds = [RACE_EVENT_SEED, race_id.to_le_bytes().as_ref()],
+        bump
+    )]
+    pub race_event: Box<Account<'info, RaceEvent>>,
+    pub system_program: Program<'info, System>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+#[event]
+pub struct RaceCreated {
+    pub race_id: u64,
+    pub entry_close_slot: u64,
+    pub purse: u64,
+}
+
+pub fn create_race(
+    ctx: Context<CreateRace>,
+    race_id: u64,
+    entry_close_slot: u64,
+    purse: u64,
+) -> Result<()> {
+    let slot = Clock::get()?.slot;
+
+    require!(entry_close_slot > slot, HorseGameError::InvalidRaceConfig);
+
+    // Hold the purse back from emissions so prizes never eat into what racers
+    // and stakers have accrued; accrue both pools first so they keep what they
+    // earned under the old supply
+    let gs = &mut ctx.accounts.global_state;
+    update_pool(
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
+    )?;
+    update_staking_pool(gs, slot);
+    require!(
+        purse <= unreserved_supply(gs),
+        HorseGameError::RacePurseUnavailable
+    );
+    gs.race_purses_reserved = gs.race_purses_reserved.saturating_add(purse);
+
+    let race = &mut ctx.accounts.race_event;
+    race.race_id = race_id;
+    race.entry_close_slot = entry_close_slot;
+    race.purse = purse;
+    race.entrant_count = 0;
+    race.entrants = [RaceEntrant::default(); MAX_RACE_ENTRANTS as usize];
+    race.settled = false;
+    race.podium_count = 0;
+    race.podium = [0u8; RACE_PODIUM_SIZE];
+    race.prizes_claimed = [false; RACE_PODIUM_SIZE];
+    race.cancelled = false;
+
+    emit!(RaceCreated {
+        race_id,
+        entry_close_slot,
+        purse,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// REGISTER FOR RACE EVENT
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(horse_index: u8)]
+pub struct RegisterForRace<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePendingBreeding,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        constraint = !race_event.settled @ HorseGameError::RaceAlreadySettled,
+    )]
+    pub race_event: Box<Account<'info, RaceEvent>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[event]
+pub struct RaceEntryRegistered {
+    pub race_id: u64,
+    pub player: Pubkey,
+    pub horse_index: u8,
+    pub horse_id: u16,
+    pub entrant_index: u8,
+}
+
+pub fn register_for_race(ctx: Context<RegisterForRace>, horse_index: u8) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let race = &mut ctx.accounts.race_event;
+    let wallet = ctx.accounts.player_wallet.key();
+
+    require!(
+        ctx.accounts.global_state.production_enabled,
+        HorseGameError::ProductionDisabled
+    );
+    require!(slot < race.entry_close_slot, HorseGameError::RaceEntryClosed);
+    require!(
+        race.entrant_count < MAX_RACE_ENTRANTS,
+        HorseGameError::RaceFull
+    );
+    require!(
+        race.find_entrant(&wallet).is_none(),
+        HorseGameError::AlreadyRegisteredForRace
+    );
+
+    validate_horse_index(horse_index, player.horse_count as usize)?;
+
+    let horse = player.horses[horse_index as usize];
+    let entrant_index = race.entrant_count;
+    race.entrants[entrant_index as usize] = RaceEntrant {
+        wallet,
+        horse_id: horse.id,
+        speed: horse.speed,
+        stamina_cost: horse.stamina_cost,
+    };
+    race.entrant_count += 1;
+
+    player.race_entry_lock_slot = player.race_entry_lock_slot.max(race.entry_close_slot);
+    player.total_races_entered = player.total_races_entered.saturating_add(1);
+
+    emit!(RaceEntryRegistered {
+        race_id: race.race_id,
+        player: player.key(),
+        horse_index,
+        horse_id: horse.id,
+        entrant_index,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// SETTLE RACE EVENT (permissionless reveal)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct SettleRace<'info> {
+    pub settler: Signer<'info>,
+    #[account(
+        mut,
+        constraint = !race_event.settled @ HorseGameError::RaceAlreadySettled,
+        constraint = !race_event.cancelled @ HorseGameError::RaceCancelled,
+    )]
+    pub race_event: Box<Account<'info, RaceEvent>>,
+    #[account(mut, seeds = [GLOBAL_STATE_SEED], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    /// CHECK: Checked manually
+    pub slot_hashes: AccountInfo<'info>,
+}
+
+#[event]
+pub struct RaceSettled {
+    pub race_id: u64,
+    pub reveal_slot: u64,
+    pub entrant_count: u8,
+    pub podium_wallets: Vec<Pubkey>,
+    pub podium_horse_ids: Vec<u16>,
+}
+
+/// Races always reveal from SlotHashes, whatever `randomness_source` is: they
+/// have no player to request oracle randomness, and `cancel_race` depends on
+/// the reveal having expired from the sysvar
+pub fn settle_race(ctx: Context<SettleRace>) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let race = &mut ctx.accounts.race_event;
+
+    let reveal_slot = race.entry_close_slot + MIN_RANDOMNESS_DELAY_SLOTS;
+    require!(slot >= reveal_slot, HorseGameError::RaceEntryStillOpen);
+
+    // A skipped reveal slot falls through to the next block; once that is gone
+    // from the sysvar the race can only be cancelled
+    let (reveal_slot, random_value) =
+        match lookup_first_slot_hash(&ctx.accounts.slot_hashes, reveal_slot)? {
+            FirstSlotHashLookup::Found { slot, hash } => (slot, hash),
+            FirstSlotHashLookup::Pending => return err!(HorseGameError::RandomnessNotResolved),
+            FirstSlotHashLookup::Expired => return err!(HorseGameError::SlotNotFound),
+        };
+
+    let weights: Vec<u64> = race.entrants[..race.entrant_count as usize]
+        .iter()
+        .map(|entrant| race_weight(entrant.speed, entrant.stamina_cost))
+        .collect();
+    let podium = draw_race_podium(&weights, &random_value);
+
+    race.podium_count = podium.len() as u8;
+    race.podium[..podium.len()].copy_from_slice(&podium);
+    race.settled = true;
+
+    // Places nobody finished in, and rounding, go back to emissions
+    let awarded: u64 = (0..podium.len())
+        .map(|place| race_prize(race.purse, place))
+        .sum();
+    let gs = &mut ctx.accounts.global_state;
+    gs.race_purses_reserved = gs
+        .race_purses_reserved
+        .saturating_sub(race.purse - awarded);
+
+    emit!(RaceSettled {
+        race_id: race.race_id,
+        reveal_slot,
+        entrant_count: race.entrant_count,
+        podium_wallets: podium
+            .iter()
+            .map(|&index| race.entrants[index as usize].wallet)
+            .collect(),
+        podium_horse_ids: podium
+            .iter()
+            .map(|&index| race.entrants[index as usize].horse_id)
+            .collect(),
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// CANCEL RACE EVENT (permissionless, once the draw has expired)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct CancelRace<'info> {
+    pub canceller: Signer<'info>,
+    #[account(
+        mut,
+        constraint = !race_event.settled @ HorseGameError::RaceAlreadySettled,
+        constraint = !race_event.cancelled @ HorseGameError::RaceCancelled,
+    )]
+    pub race_event: Box<Account<'info, RaceEvent>>,
+    #[account(mut, seeds = [GLOBAL_STATE_SEED], bump)]
+    pub global_state: Account<'info, GlobalState>,
+    /// CHECK: Checked manually
+    pub slot_hashes: AccountInfo<'info>,
+}
+
+#[event]
+pub struct RaceCancelled {
+    pub race_id: u64,
+    pub entrant_count: u8,
+}
+
+pub fn cancel_race(ctx: Context<CancelRace>) -> Result<()> {
+    let race = &mut ctx.accounts.race_event;
+
+    let reveal_slot = race.entry_close_slot + MIN_RANDOMNESS_DELAY_SLOTS;
+    match lookup_first_slot_hash(&ctx.accounts.slot_hashes, reveal_slot)? {
+        FirstSlotHashLookup::Expired => {}
+        _ => return err!(HorseGameError::RaceStillSettleable),
+    }
+
+    race.cancelled = true;
+
+    // Nothing is paid, so the whole purse goes back to emissions
+    let gs = &mut ctx.accounts.global_state;
+    gs.race_purses_reserved = gs.race_purses_reserved.saturating_sub(race.purse);
+
+    emit!(RaceCancelled {
+        race_id: race.race_id,
+        entrant_count: race.entrant_count,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// CLAIM RACE PRIZE
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct ClaimRacePrize<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        mut,
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        mut,
+        constraint = race_event.settled @ HorseGameError::RaceNotSettled,
+    )]
+    pub race_event: Box<Account<'info, RaceEvent>>,
+    #[account(
+        mut,
+        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
+        bump,
+    )]
+    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    #[account(
+        mut,
+        constraint = player_token_account.mint == global_state.token_mint,
+        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    /// Required once `global_state.emission_schedule_enabled` is set
+    #[account(seeds = [EMISSION_SCHEDULE_SEED], bump)]
+    pub emission_schedule: Option<Box<Account<'info, EmissionSchedule>>>,
+    /// Required once `global_state.speed_history_enabled` is set
+    #[account(mut, seeds = [SPEED_HISTORY_SEED], bump)]
+    pub speed_history: Option<Box<Account<'info, SpeedHistory>>>,
+}
+
+#[event]
+pub struct RacePrizeClaimed {
+    pub race_id: u64,
+    pub player: Pubkey,
+    pub place: u8,
+    pub amount: u64,
+}
+
+pub fn claim_race_prize(ctx: Context<ClaimRacePrize>) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &mut ctx.accounts.global_state;
+    let race = &mut ctx.accounts.race_event;
+
+    let entrant_index = race
+        .find_entrant(&ctx.accounts.player_wallet.key())
+        .ok_or(HorseGameError::NoRacePrize)?;
+    let place = race.podium[..race.podium_count as usize]
+        .iter()
+        .position(|&index| index as usize == entrant_index)
+        .ok_or(HorseGameError::NoRacePrize)?;
+    require!(!race.prizes_claimed[place], HorseGameError::NoRacePrize);
+
+    update_pool(
+        gs,
+        PoolAccounts {
+            emission_schedule: &ctx.accounts.emission_schedule,
+            speed_history: &mut ctx.accounts.speed_history,
+        },
+        slot,
+    )?;
+
+    // Reserved from the emission supply at creation; an underfunded vault
+    // fails the claim so it can be retried once the vault is topped up
+    let prize = race_prize(race.purse, place);
+    require!(
+        prize <= ctx.accounts.rewards_vault.amount,
+        HorseGameError::InsufficientTokens
+    );
+    gs.race_purses_reserved = gs.race_purses_reserved.saturating_sub(prize);
+
+    race.prizes_claimed[place] = true;
+    if place == 0 {
+        player.total_race_wins = player.total_race_wins.saturating_add(1);
+    }
+
+    if prize > 0 {
+        gs.cumulative_rewards = gs.cumulative_rewards.saturating_add(prize);
+        player.total_rewards = player.total_rewards.saturating_add(prize);
+
+        let seeds = &[
+            GLOBAL_STATE_SEED,
+            &[ctx.bumps.global_state],
+        ];
+        let signer = &[&seeds[..]];
+
+        token_interface::transfer_checked(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: ctx.accounts.rewards_vault.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.player_token_account.to_account_info(),
+                    authority: gs.to_account_info(),
+                },
+                signer,
+            ),
+            prize,
+            ctx.accounts.token_mint.decimals,
+        )?;
+    }
+
+    emit!(RacePrizeClaimed {
+        race_id: race.race_id,
+        player: player.key(),
+        place: place as u8,
+        amount: prize,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// MINT HORSE NFT (horse leaves the Player account)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(horse_index: u8)]
+pub struct MintHorseNft<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePendingBreeding,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    /// Fresh keypair for the horse NFT mint
+    #[account(
+        init,
+        payer = player_wallet,
+        mint::decimals = 0,
+        mint::authority = global_state,
+        mint::token_program = nft_token_program,
+        extensions::metadata_pointer::authority = global_state,
+        extensions::metadata_pointer::metadata_address = horse_mint,
+    )]
+    pub horse_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        init,
+        payer = player_wallet,
+        space = 8 + 32 + 6 + 32 + 8,
+        seeds = [HORSE_NFT_SEED, horse_mint.key().as_ref()],
+        bump
+    )]
+    pub horse_nft: Box<Account<'info, HorseNft>>,
+    #[account(
+        init,
+        payer = player_wallet,
+        associated_token::mint = horse_mint,
+        associated_token::authority = player_wallet,
+        associated_token::token_program = nft_token_program,
+    )]
+    pub player_horse_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub nft_token_program: Program<'info, Token2022>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct HorseNftMinted {
+    pub player: Pubkey,
+    pub horse_mint: Pubkey,
+    pub horse_index: u8,
+    pub horse_id: u16,
+    pub grade: u8,
+    pub speed: u16,
+    pub stamina_cost: u8,
+}
+
+pub fn mint_horse_nft(ctx: Context<MintHorseNft>, horse_index: u8) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let gs = &ctx.accounts.global_state;
+
+    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
+    validate_horse_index(horse_index, player.horse_count as usize)?;
+    require!(
+        !player.is_horse_racing(horse_index),
+        HorseGameError::HorseIsRacing
+    );
+
+    player.require_horses_unlocked(slot)?;
+    let horse = player.horses[horse_index as usize];
+    player.batch_remove_horses(&[horse_index])?;
+
+    let horse_mint_key = ctx.accounts.horse_mint.key();
+    let metadata = TokenMetadata {
+        update_authority: OptionalNonZeroPubkey::try_from(Some(gs.key()))?,
+        mint: horse_mint_key,
+        name: format!("{}{}", HORSE_NFT_NAME_PREFIX, horse.id),
+        symbol: HORSE_NFT_SYMBOL.to_string(),
+        uri: String::new(),
+        additional_metadata: vec![
+            ("id".to_string(), horse.id.to_string()),
+            ("grade".to_string(), grade_name(horse.grade).to_string()),
+            ("speed".to_string(), horse.speed.to_string()),
+            ("stamina".to_string(), horse.stamina_cost.to_string()),
+        ],
+    };
+
+    // Token-2022 reallocs the mint for the metadata TLV but does not fund it
+    let mint_info = ctx.accounts.horse_mint.to_account_info();
+    let new_len = mint_info.data_len() + metadata.tlv_size_of()?;
+    let extra_lamports = Rent::get()?
+        .minimum_balance(new_len)
+        .saturating_sub(mint_info.lamports());
+    if extra_lamports > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                ctx.accounts.system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: ctx.accounts.player_wallet.to_account_info(),
+                    to: mint_info.clone(),
+                },
+            ),
+            extra_lamports,
+        )?;
+    }
+
+    let seeds = &[
+        GLOBAL_STATE_SEED,
+        &[ctx.bumps.global_state],
+    ];
+    let signer = &[&seeds[..]];
+    let nft_program = ctx.accounts.nft_token_program.to_account_info();
+    let gs_info = gs.to_account_info();
+
+    token_metadata_initialize(
+        CpiContext::new_with_signer(
+            nft_program.clone(),
+            TokenMetadataInitialize {
+                program_id: nft_program.clone(),
+                metadata: mint_info.clone(),
+                update_authority: gs_info.clone(),
+                mint_authority: gs_info.clone(),
+                mint: mint_info.clone(),
+            },
+            signer,
+        ),
+        metadata.name.clone(),
+        metadata.symbol.clone(),
+        metadata.uri.clone(),
+    )?;
+
+    for (key, value) in metadata.additional_metadata.iter() {
+        token_metadata_update_field(
+            CpiContext::new_with_signer(
+                nft_program.clone(),
+                TokenMetadataUpdateField {
+                    program_id: nft_program.clone(),
+                    metadata: mint_info.clone(),
+                    update_authority: gs_info.clone(),
+                },
+                signer,
+            ),
+            Field::Key(key.clone()),
+            value.clone(),
+        )?;
+    }
+
+    token_2022::mint_to(
+        CpiContext::new_with_signer(
+            nft_program.clone(),
+            token_2022::MintTo {
+                mint: mint_info.clone(),
+                to: ctx.accounts.player_horse_token_account.to_account_info(),
+                authority: gs_info.clone(),
+            },
+            signer,
+        ),
+        1,
+    )?;
+
+    // Fix the supply at one
+    token_2022::set_authority(
+        CpiContext::new_with_signer(
+            nft_program,
+            token_2022::SetAuthority {
+                current_authority: gs_info,
+                account_or_mint: mint_info,
+            },
+            signer,
+        ),
+        AuthorityType::MintTokens,
+        None,
+    )?;
+
+    let horse_nft = &mut ctx.accounts.horse_nft;
+    horse_nft.mint = horse_mint_key;
+    horse_nft.horse = horse;
+    horse_nft.minted_by = ctx.accounts.player_wallet.key();
+    horse_nft.minted_slot = slot;
+
+    emit!(HorseNftMinted {
+        player: player.key(),
+        horse_mint: horse_mint_key,
+        horse_index,
+        horse_id: horse.id,
+        grade: horse.grade,
+        speed: horse.speed,
+        stamina_cost: horse.stamina_cost,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// DEPOSIT HORSE NFT (burn the NFT, horse returns to a Player account)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+pub struct DepositHorseNft<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePendingBreeding,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    #[account(
+        mut,
+        constraint = horse_mint.key() == horse_nft.mint @ HorseGameError::InvalidHorseNft,
+    )]
+    pub horse_mint: Box<InterfaceAccount<'info, Mint>>,
+    #[account(
+        mut,
+        close = player_wallet,
+        seeds = [HORSE_NFT_SEED, horse_mint.key().as_ref()],
+        bump
+    )]
+    pub horse_nft: Box<Account<'info, HorseNft>>,
+    #[account(
+        mut,
+        token::mint = horse_mint,
+        token::authority = player_wallet,
+        token::token_program = nft_token_program,
+    )]
+    pub player_horse_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub nft_token_program: Program<'info, Token2022>,
+}
+
+#[event]
+pub struct HorseNftDeposited {
+    pub player: Pubkey,
+    pub horse_mint: Pubkey,
+    pub horse_index: u8,
+    pub horse_id: u16,
+}
+
+pub fn deposit_horse_nft(ctx: Context<DepositHorseNft>) -> Result<()> {
+    let player = &mut ctx.accounts.player;
+    let horse = ctx.accounts.horse_nft.horse;
+
+    require!(
+        ctx.accounts.global_state.production_enabled,
+        HorseGameError::ProductionDisabled
+    );
+
+    token_2022::burn(
+        CpiContext::new(
+            ctx.accounts.nft_token_program.to_account_info(),
+            token_2022::Burn {
+                mint: ctx.accounts.horse_mint.to_account_info(),
+                from: ctx.accounts.player_horse_token_account.to_account_info(),
+                authority: ctx.accounts.player_wallet.to_account_info(),
+            },
+        ),
+        1,
+    )?;
+
+    let horse_index = player.horse_count;
+    player.add_horse(horse)?;
+
+    emit!(HorseNftDeposited {
+        player: player.key(),
+        horse_mint: ctx.accounts.horse_mint.key(),
+        horse_index,
+        horse_id: horse.id,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// TRANSFER HORSE (player to player)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(horse_index: u8)]
+pub struct TransferHorse<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePendingBreeding,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    /// CHECK: Wallet that owns the recipient Player account
+    #[account(
+        constraint = recipient_wallet.key() != player_wallet.key() @ HorseGameError::SelfTransferNotAllowed
+    )]
+    pub recipient_wallet: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = recipient_player.owner == recipient_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = recipient_player.can_receive_horse() @ HorseGameError::RecipientActionPending,
+        seeds = [PLAYER_SEED, recipient_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub recipient_player: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+}
+
+#[event]
+pub struct HorseTransferred {
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub horse_index: u8,
+    pub recipient_horse_index: u8,
+    pub horse_id: u16,
+}
+
+pub fn transfer_horse(ctx: Context<TransferHorse>, horse_index: u8) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+    let recipient = &mut ctx.accounts.recipient_player;
+
+    require!(
+        ctx.accounts.global_state.production_enabled,
+        HorseGameError::ProductionDisabled
+    );
+    validate_horse_index(horse_index, player.horse_count as usize)?;
+    require!(
+        !player.is_horse_racing(horse_index),
+        HorseGameError::HorseIsRacing
+    );
+    require!(
+        recipient.horse_count < MAX_HORSES_PER_PLAYER,
+        HorseGameError::StableCapacityExceeded
+    );
+
+    player.require_horses_unlocked(slot)?;
+    let horse = player.horses[horse_index as usize];
+    player.batch_remove_horses(&[horse_index])?;
+
+    let recipient_horse_index = recipient.horse_count;
+    recipient.add_horse(horse)?;
+
+    emit!(HorseTransferred {
+        from: ctx.accounts.player_wallet.key(),
+        to: ctx.accounts.recipient_wallet.key(),
+        horse_index,
+        recipient_horse_index,
+        horse_id: horse.id,
+    });
+
+    Ok(())
+}
+
+/// ────────────────────────────────────────────────────────────────────────────
+/// LIST HORSE (escrow a horse for sale)
+/// ────────────────────────────────────────────────────────────────────────────
+#[derive(Accounts)]
+#[instruction(horse_index: u8)]
+pub struct ListHorse<'info> {
+    #[account(mut)]
+    pub player_wallet: Signer<'info>,
+    #[account(
+        mut,
+        constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
+        constraint = player.pending_action == PendingRandomAction::None @ HorseGameError::HorsePendingBreeding,
+        seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
+        bump
+    )]
+    pub player: Box<Account<'info, Player>>,
+    #[account(
+        seeds = [GLOBAL_STATE_SEED],
+        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
+        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
+        bump,
+    )]
+    pub global_state: Account<'info, GlobalState>,
+    #[account(
+        init,
+        payer = player_wallet,
+        space = 8 + 32 + 8 + 6 + 8 + 8,
+        seeds = [LISTING_SEED, player_wallet.key().as_ref(), (player.listings_created as u64).to_le_bytes().as_ref()],
+        bump
+    )]
+    pub listing: Box<Account<'info, HorseListing>>,
+    #[account()]
+    pub token_mint: InterfaceAccount<'info, Mint>,
+    pub system_program: Program<'info, System>,
+}
+
+#[event]
+pub struct HorseListed {
+    pub seller: Pubkey,
+    pub listing: Pubkey,
+    pub listing_id: u64,
+    pub horse_id: u16,
+    pub grade: u8,
+    pub price: u64,
+}
+
+pub fn list_horse(ctx: Context<ListHorse>, horse_index: u8, price: u64) -> Result<()> {
+    let slot = Clock::get()?.slot;
+    let player = &mut ctx.accounts.player;
+
+    require!(
+        ctx.accounts.global_state.production_enabled,
+        HorseGameError::ProductionDisabled
+    );
+    require!(price > 0, HorseGameError::InvalidListingPrice);
+    validate_horse_index(horse_index, player.horse_count as usize)?;
+    require!(
+        !player.is_horse_racing(horse_index),
+        HorseGameError::HorseIsRacing
+    );
 
-    gs.total_breeding_attempts = gs.total_breeding_attempts.saturating_add(1);
+    player.require_horses_unlocked(slot)?;
+    let horse = player.horses[horse_index as usize];
+    player.batch_remove_horses(&[horse_index])?;
+
+    let listing_id = player.listings_created as u64;
+    player.listings_created = player.listings_created.saturating_add(1);
+
+    let listing = &mut ctx.accounts.listing;
+    listing.seller = ctx.accounts.player_wallet.key();
+    listing.listing_id = listing_id;
+    listing.horse = horse;
+    listing.price = price;
+    listing.listed_slot = slot;
+
+    emit!(HorseListed {
+        seller: listing.seller,
+        listing: listing.key(),
+        listing_id,
+        horse_id: horse.id,
+        grade: horse.grade,
+        price,
+    });
 
     Ok(())
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-/// BREED HORSES (settle phase)
+/// CANCEL LISTING (horse returns to the seller)
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct BreedHorsesSettle<'info> {
+pub struct CancelListing<'info> {
     #[account(mut)]
     pub player_wallet: Signer<'info>,
     #[account(
         mut,
         constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
-        constraint = matches!(player.pending_action, PendingRandomAction::Breeding { .. }) @ HorseGameError::NoBreedingPending,
+        constraint = player.can_receive_horse() @ HorseGameError::RecipientActionPending,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
     pub player: Box<Account<'info, Player>>,
     #[account(
         mut,
-        seeds = [GLOBAL_STATE_SEED],
-        constraint = global_state.token_initialized @ HorseGameError::TokenNotInitialized,
-        constraint = global_state.token_mint == token_mint.key() @ HorseGameError::InvalidTokenMint,
-        bump,
-    )]
-    pub global_state: Account<'info, GlobalState>,
-    #[account(
-        mut,
-        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
-        bump,
-    )]
-    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
-    #[account(
-        mut,
-        constraint = player_token_account.mint == global_state.token_mint,
-        constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
+        close = player_wallet,
+        constraint = listing.seller == player_wallet.key() @ HorseGameError::Unauthorized,
     )]
-    pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    pub listing: Box<Account<'info, HorseListing>>,
     #[account()]
     pub token_mint: InterfaceAccount<'info, Mint>,
-    pub token_program: Interface<'info, TokenInterface>,
-    /// CHECK: Checked manually
-    pub slot_hashes: AccountInfo<'info>,
 }
 
-pub fn breed_horses_settle(ctx: Context<BreedHorsesSettle>) -> Result<()> {
-    let clock: Clock = Clock::get()?;
-    let player = &mut ctx.accounts.player;
-    let gs = &mut ctx.accounts.global_state;
-
-    require!(
-        clock.slot >= player.commit_slot + MIN_RANDOMNESS_DELAY_SLOTS,
-        HorseGameError::RandomnessNotResolved
-    );
-    let reveal_slot = player.commit_slot + MIN_RANDOMNESS_DELAY_SLOTS;
-
-    let sysvar_slot_history = &ctx.accounts.slot_hashes;
-    require!(
-        sysvar_slot_history.key == &slot_hashes::id(),
-        HorseGameError::InvalidSlotHashes
-    );
-
-    let data = sysvar_slot_history.try_borrow_data()?;
-    let num_slot_hashes = u64::from_le_bytes(data[0..8].try_into().unwrap());
-    let mut pos = 8;
-    let mut found_hash = None;
-    for _ in 0..num_slot_hashes {
-        let slot = u64::from_le_bytes(data[pos..pos + 8].try_into().unwrap());
-        pos += 8;
-        let hash = &data[pos..pos + 32];
-        if slot == reveal_slot {
-            found_hash = Some(hash);
-            break;
-        }
-        pos += 32;
-    }
-
-    let random_value = found_hash.ok_or(HorseGameError::SlotNotFound)?;
-
-    settle_and_mint_rewards(
-        player,
-        gs,
-        clock.slot,
-        &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
-        &ctx.accounts.rewards_vault,
-        &ctx.accounts.token_program.to_account_info(),
-        ctx.bumps.global_state,
-        ctx.accounts.token_mint.decimals,
-    )?;
-
-    let (horse_indices_array, horse_count) = if let PendingRandomAction::Breeding {
-        horse_indices,
-        horse_count,
-    } = player.pending_action
-    {
-        (horse_indices, horse_count)
-    } else {
-        return Err(HorseGameError::NoBreedingPending.into());
-    };
-
-    let mut successful_offspring = 0u8;
-    let mut new_horses: Vec<(u16, u8, u16, u8)> = Vec::new();
-
-    for i in 0..horse_count {
-        let horse_index = horse_indices_array[i as usize];
-
-        if (horse_index as usize) >= (player.horse_count as usize) {
-            continue;
-        }
-
-        let horse = &player.horses[horse_index as usize];
-        let current_grade = horse.grade;
-
-        let random_byte_index = (i as usize) % random_value.len();
-        let random_byte = random_value[random_byte_index];
-
-        // 20% chance to get offspring of next grade
-        if random_byte < 51 {
-            if let Some(next_grade) = get_next_grade(current_grade) {
-                let horses_of_next_grade: Vec<&(u16, u8, u16, u8)> = HORSE_DATA
-                    .iter()
-                    .filter(|(_, horse_grade, _, _)| *horse_grade == next_grade)
-                    .collect();
-
-                if !horses_of_next_grade.is_empty() {
-                    let mut random_bytes: [u8; 4] = [0; 4];
-                    let start_idx = (i as usize * 4) % (random_value.len() - 3);
-                    random_bytes.copy_from_slice(&random_value[start_idx..start_idx + 4]);
-                    let random_u32 = u32::from_le_bytes(random_bytes);
-
-                    let horse_index_in_grade = (random_u32 as usize) % horses_of_next_grade.len();
-                    let (horse_id, _, speed, stamina_cost) =
-                        horses_of_next_grade[horse_index_in_grade];
-
-                    new_horses.push((*horse_id, next_grade, *speed, *stamina_cost));
-                    successful_offspring += 1;
-                }
-            }
-        }
-    }
-
-    let indices_to_remove: Vec<u8> = horse_indices_array[0..horse_count as usize].to_vec();
-    player.batch_remove_horses(&indices_to_remove)?;
-
-    for (horse_id, grade, speed, stamina_cost) in new_horses {
-        require!(
-            (player.horse_count as usize) < MAX_HORSES_PER_PLAYER as usize,
-            HorseGameError::StableCapacityExceeded
-        );
-
-        let new_horse = Horse {
-            id: horse_id,
-            grade,
-            speed,
-            stamina_cost,
-        };
-        player.add_horse(new_horse)?;
-    }
-
-    player.pending_action = PendingRandomAction::None;
-    player.commit_slot = 0;
+#[event]
+pub struct HorseListingCancelled {
+    pub seller: Pubkey,
+    pub listing: Pubkey,
+    pub listing_id: u64,
+    pub horse_id: u16,
+}
 
-    player.total_horses_bred = player
-        .total_horses_bred
-        .saturating_add(horse_count as u64);
+pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+    let player = &mut ctx.accounts.player;
+    let listing = &ctx.accounts.listing;
 
-    if successful_offspring > 0 {
-        player.successful_breeding = player
-            .successful_breeding
-            .saturating_add(successful_offspring as u64);
-        gs.total_successful_breeding = gs
-            .total_successful_breeding
-            .saturating_add(successful_offspring as u64);
-    }
+    player.add_horse(listing.horse)?;
 
-    emit!(HorsesBred {
-        player: player.key(),
-        successful_offspring,
-        total_bred: horse_count,
+    emit!(HorseListingCancelled {
+        seller: listing.seller,
+        listing: listing.key(),
+        listing_id: listing.listing_id,
+        horse_id: listing.horse.id,
     });
 
     Ok(())
 }
 
 /// ────────────────────────────────────────────────────────────────────────────
-/// CANCEL PENDING ACTION
+/// BUY HORSE
 /// ────────────────────────────────────────────────────────────────────────────
 #[derive(Accounts)]
-pub struct CancelPendingAction<'info> {
+pub struct BuyHorse<'info> {
     #[account(mut)]
     pub player_wallet: Signer<'info>,
     #[account(
         mut,
         constraint = player.owner == player_wallet.key() @ HorseGameError::Unauthorized,
-        constraint = player.pending_action != PendingRandomAction::None @ HorseGameError::NoPendingAction,
+        constraint = player.can_receive_horse() @ HorseGameError::RecipientActionPending,
         seeds = [PLAYER_SEED, player_wallet.key().as_ref(), token_mint.key().as_ref()],
         bump
     )]
@@ -1632,54 +5852,109 @@ pub struct CancelPendingAction<'info> {
     pub global_state: Account<'info, GlobalState>,
     #[account(
         mut,
-        seeds = [REWARDS_VAULT_SEED, token_mint.key().as_ref()],
-        bump,
+        close = seller_wallet,
+        constraint = listing.seller != player_wallet.key() @ HorseGameError::CannotBuyOwnListing,
     )]
-    pub rewards_vault: InterfaceAccount<'info, TokenAccount>,
+    pub listing: Box<Account<'info, HorseListing>>,
+    /// CHECK: Receives the listing rent; must be the listing's seller
+    #[account(
+        mut,
+        constraint = seller_wallet.key() == listing.seller @ HorseGameError::Unauthorized
+    )]
+    pub seller_wallet: AccountInfo<'info>,
+    #[account(
+        mut,
+        constraint = seller_token_account.mint == global_state.token_mint,
+        constraint = seller_token_account.owner == listing.seller @ HorseGameError::InvalidTokenAccountOwner
+    )]
+    pub seller_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
     #[account(
         mut,
         constraint = player_token_account.mint == global_state.token_mint,
         constraint = player_token_account.owner == player_wallet.key() @ HorseGameError::InvalidTokenAccountOwner
     )]
     pub player_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
-    #[account()]
+    #[account(
+        mut,
+        constraint = fees_token_account.mint == global_state.token_mint,
+        constraint = fees_token_account.owner == global_state.fees_wallet @ HorseGameError::Unauthorized
+    )]
+    pub fees_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+    #[account(mut)]
     pub token_mint: InterfaceAccount<'info, Mint>,
     pub token_program: Interface<'info, TokenInterface>,
 }
 
-pub fn cancel_pending_action(ctx: Context<CancelPendingAction>) -> Result<()> {
+#[event]
+pub struct HorseSold {
+    pub seller: Pubkey,
+    pub buyer: Pubkey,
+    pub listing: Pubkey,
+    pub listing_id: u64,
+    pub horse_id: u16,
+    pub price: u64,
+    pub marketplace_fee: u64,
+}
+
+pub fn buy_horse(ctx: Context<BuyHorse>) -> Result<()> {
     let player = &mut ctx.accounts.player;
-    let clock = Clock::get()?;
+    let gs = &mut ctx.accounts.global_state;
+    let listing = &ctx.accounts.listing;
+    let price = listing.price;
 
-    settle_and_mint_rewards(
+    require!(gs.production_enabled, HorseGameError::ProductionDisabled);
+    require!(
+        ctx.accounts.player_token_account.amount >= price,
+        HorseGameError::InsufficientTokens
+    );
+
+    let marketplace_fee = price
+        .saturating_mul(gs.marketplace_fee_bps as u64)
+        .saturating_div(BPS_DENOMINATOR);
+    let seller_proceeds = price.saturating_sub(marketplace_fee);
+
+    player.add_horse(listing.horse)?;
+    player.total_tokens_spent = player.total_tokens_spent.saturating_add(price);
+
+    handle_fee_transfers(
         player,
-        &mut ctx.accounts.global_state,
-        clock.slot,
+        gs,
+        marketplace_fee,
         &ctx.accounts.player_token_account.to_account_info(),
-        &ctx.accounts.token_mint.to_account_info(),
-        &ctx.accounts.rewards_vault,
+        &ctx.accounts.fees_token_account.to_account_info(),
+        None,
+        &ctx.accounts.player_wallet.to_account_info(),
         &ctx.accounts.token_program.to_account_info(),
-        ctx.bumps.global_state,
+        &ctx.accounts.token_mint.to_account_info(),
+        false,
         ctx.accounts.token_mint.decimals,
     )?;
 
-    require!(
-        clock.slot > player.commit_slot + CANCEL_TIMEOUT_SLOTS,
-        HorseGameError::CancelTimeoutNotExpired
-    );
-
-    if let PendingRandomAction::Breeding {
-        horse_indices,
-        horse_count,
-    } = player.pending_action.clone()
-    {
-        let mut indices_to_remove: Vec<u8> = horse_indices[0..horse_count as usize].to_vec();
-        indices_to_remove.sort_by(|a, b| b.cmp(a));
-        player.batch_remove_horses(&indices_to_remove)?;
+    if seller_proceeds > 0 {
+        token_interface::transfer_checked(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                token_interface::TransferChecked {
+                    from: ctx.accounts.player_token_account.to_account_info(),
+                    mint: ctx.accounts.token_mint.to_account_info(),
+                    to: ctx.accounts.seller_token_account.to_account_info(),
+                    authority: ctx.accounts.player_wallet.to_account_info(),
+                },
+            ),
+            seller_proceeds,
+            ctx.accounts.token_mint.decimals,
+        )?;
     }
 
-    player.pending_action = PendingRandomAction::None;
-    player.commit_slot = 0;
+    emit!(HorseSold {
+        seller: listing.seller,
+        buyer: ctx.accounts.player_wallet.key(),
+        listing: listing.key(),
+        listing_id: listing.listing_id,
+        horse_id: listing.horse.id,
+        price,
+        marketplace_fee,
+    });
 
     Ok(())
 }
@@ -1782,3 +6057,318 @@ fn handle_fee_transfers<'info>(
     Ok(())
 }
 
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const START: u64 = 1_000;
+
+    fn test_global_state() -> GlobalState {
+        let mut gs = GlobalState::deserialize(&mut &[0u8; 1024][..]).unwrap();
+        gs.start_slot = START;
+        gs.last_reward_slot = START;
+        gs.total_supply = u64::MAX / 2;
+        gs.total_speed = 1_000;
+        gs
+    }
+
+    /// Runs the accumulator from `START` to `end`, updating at every slot in `updates`
+    fn accrue(schedule: Option<&EmissionSchedule>, updates: &[u64], end: u64) -> GlobalState {
+        let mut gs = test_global_state();
+        for &slot in updates.iter().chain(std::iter::once(&end)) {
+            accrue_pool_rewards(&mut gs, schedule, slot);
+        }
+        gs
+    }
+
+    fn expected_default_emission(from: u64, to: u64) -> u128 {
+        (from..to)
+            .step_by(1_000)
+            .map(|slot| calculate_current_reward_rate(slot, START) as u128 * 1_000)
+            .sum()
+    }
+
+    #[test]
+    fn frequent_and_infrequent_updates_accrue_identically() {
+        let end = START + 100 * SLOTS_PER_DAY;
+        // Irregular steps so updates land on both sides of every stage boundary
+        let frequent: Vec<u64> = (1..)
+            .map(|i: u64| START + i * 7_919 + (i * i) % 1_013)
+            .take_while(|&slot| slot < end)
+            .collect();
+
+        let infrequent = accrue(None, &[], end);
+        let daily = accrue(
+            None,
+            &(1..100).map(|day| START + day * SLOTS_PER_DAY).collect::<Vec<_>>(),
+            end,
+        );
+        let frequent = accrue(None, &frequent, end);
+
+        assert_eq!(infrequent.cumulative_rewards, daily.cumulative_rewards);
+        assert_eq!(infrequent.cumulative_rewards, frequent.cumulative_rewards);
+        assert_eq!(infrequent.acc_tokens_per_speed, frequent.acc_tokens_per_speed);
+        assert_eq!(
+            infrequent.cumulative_rewards as u128,
+            expected_default_emission(START, end)
+        );
+    }
+
+    #[test]
+    fn window_spanning_a_stage_boundary_pays_each_stage_its_own_rate() {
+        let boundary = START + STAGE_1_DURATION_SLOTS;
+        let mut gs = test_global_state();
+        accrue_pool_rewards(&mut gs, None, boundary - 10);
+        let before = gs.cumulative_rewards;
+
+        accrue_pool_rewards(&mut gs, None, boundary + 10);
+
+        assert_eq!(
+            gs.cumulative_rewards - before,
+            10 * STAGE_1_REWARD_RATE + 10 * STAGE_2_REWARD_RATE
+        );
+        assert_eq!(gs.reward_rate, STAGE_2_REWARD_RATE);
+    }
+
+    #[test]
+    fn stale_window_after_final_stage_is_still_paid() {
+        let mut gs = test_global_state();
+        accrue_pool_rewards(&mut gs, None, START + 89 * SLOTS_PER_DAY);
+        let before = gs.cumulative_rewards;
+
+        accrue_pool_rewards(&mut gs, None, START + 120 * SLOTS_PER_DAY);
+
+        assert_eq!(
+            gs.cumulative_rewards - before,
+            SLOTS_PER_DAY * STAGE_3_REWARD_RATE
+        );
+        assert_eq!(gs.reward_rate, 0);
+    }
+
+    fn test_player(staked_tokens: u64) -> Player {
+        let mut player = Player::deserialize(&mut &vec![0u8; 16_384][..]).unwrap();
+        player.staked_tokens = staked_tokens;
+        player
+    }
+
+    fn staking_global_state(total_staked_tokens: u64) -> GlobalState {
+        let mut gs = test_global_state();
+        gs.last_staking_reward_slot = START;
+        gs.token_reward_rate = 1_000;
+        gs.total_staked_tokens = total_staked_tokens;
+        gs
+    }
+
+    #[test]
+    fn staking_rewards_split_by_stake_regardless_of_update_frequency() {
+        let end = START + 10_000;
+        let mut once = staking_global_state(400);
+        update_staking_pool(&mut once, end);
+        let mut often = staking_global_state(400);
+        for slot in (START..=end).step_by(37) {
+            update_staking_pool(&mut often, slot);
+        }
+        update_staking_pool(&mut often, end);
+
+        assert_eq!(once.cumulative_rewards, 10_000 * 1_000);
+        assert_eq!(once.cumulative_rewards, often.cumulative_rewards);
+
+        let mut small = test_player(100);
+        let mut large = test_player(300);
+        assert_eq!(accrue_staking_rewards(&mut small, &once), 2_500_000);
+        assert_eq!(accrue_staking_rewards(&mut large, &once), 7_500_000);
+        // A second settle in the same slot owes nothing new
+        assert_eq!(accrue_staking_rewards(&mut large, &once), 7_500_000);
+        large.unpaid_staking_rewards = 0;
+        assert_eq!(accrue_staking_rewards(&mut large, &once), 0);
+    }
+
+    #[test]
+    fn staking_rewards_beyond_the_vault_stay_owed() {
+        let mut gs = staking_global_state(100);
+        let mut player = test_player(100);
+        update_staking_pool(&mut gs, START + 10);
+
+        // Vault only covers part of the 10k accrued: the rest is carried
+        let owed = accrue_staking_rewards(&mut player, &gs);
+        let paid = owed.min(4_000);
+        player.unpaid_staking_rewards = owed - paid;
+        assert_eq!((owed, paid, player.unpaid_staking_rewards), (10_000, 4_000, 6_000));
+
+        update_staking_pool(&mut gs, START + 20);
+        assert_eq!(accrue_staking_rewards(&mut player, &gs), 16_000);
+    }
+
+    #[test]
+    fn sol_rewards_beyond_the_wallet_stay_owed() {
+        let mut gs = test_global_state();
+        gs.acc_sol_rewards_per_token = 50 * ACC_SCALE;
+        let mut player = test_player(100);
+
+        // Wallet only covers part of the 5k accrued: the rest is carried
+        let owed = accrue_sol_rewards(&mut player, &gs);
+        let paid = owed.min(2_000);
+        player.unpaid_sol_rewards = owed - paid;
+        assert_eq!((owed, paid, player.unpaid_sol_rewards), (5_000, 2_000, 3_000));
+
+        // Unstaking stops new accrual but the carry is still paid later
+        player.staked_tokens = 0;
+        gs.acc_sol_rewards_per_token = 80 * ACC_SCALE;
+        assert_eq!(accrue_sol_rewards(&mut player, &gs), 3_000);
+    }
+
+    #[test]
+    fn staking_pool_stops_at_the_dust_threshold() {
+        let mut gs = staking_global_state(100);
+        gs.total_supply = 1_000_000;
+        gs.dust_threshold_divisor = 10;
+        gs.cumulative_rewards = 900_000;
+        update_staking_pool(&mut gs, START + 1_000);
+
+        assert_eq!(gs.acc_token_rewards_per_token, 0);
+        assert_eq!(gs.last_staking_reward_slot, START + 1_000);
+    }
+
+    #[test]
+    fn reserved_race_purses_are_never_emitted() {
+        let mut gs = test_global_state();
+        gs.total_supply = 1_000_000;
+        gs.race_purses_reserved = 400_000;
+        gs.cumulative_rewards = 500_000;
+        accrue_pool_rewards(&mut gs, None, START + 30 * SLOTS_PER_DAY);
+
+        // Emissions stop at the purses, which stay payable in full
+        assert_eq!(gs.cumulative_rewards, 600_000);
+        assert_eq!(unreserved_supply(&gs), 0);
+        let prizes: u64 = (0..RACE_PODIUM_SIZE).map(|place| race_prize(401, place)).sum();
+        assert!(prizes <= 401);
+    }
+
+    #[test]
+    fn stables_with_a_pending_settle_take_no_horses() {
+        let mut player = test_player(0);
+        assert!(player.can_receive_horse());
+
+        for pending in [
+            PendingRandomAction::PackOpening {
+                pack_type: DEFAULT_PACK_TYPE_ID,
+                horse_count: 5,
+                grade_weights: DEFAULT_PACK_GRADE_WEIGHTS,
+            },
+            PendingRandomAction::PairBreeding {
+                sire_index: 0,
+                dam_index: 1,
+                success_bps: 5_000,
+                foal_id: 1,
+            },
+        ] {
+            player.pending_action = pending;
+            assert!(!player.can_receive_horse());
+        }
+    }
+
+    #[test]
+    fn foal_ids_run_out_with_an_error_instead_of_wrapping() {
+        let mut config = BreedingConfig::deserialize(&mut &[0u8; 128][..]).unwrap();
+        assert_eq!(config.reserve_foal_id().unwrap(), FOAL_ID_START);
+
+        config.foal_ids_issued = FOAL_ID_CAPACITY - 1;
+        assert_eq!(config.reserve_foal_id().unwrap(), u16::MAX);
+        assert_eq!(
+            config.reserve_foal_id().err(),
+            Some(HorseGameError::FoalIdsExhausted.into())
+        );
+        assert_eq!(config.foal_ids_issued, FOAL_ID_CAPACITY);
+    }
+
+    #[test]
+    fn config_update_ignores_untouched_legacy_gamble_odds() {
+        // Zeroed odds, as read from the padding of an upgraded deployment
+        let mut gs = test_global_state();
+        gs.fees_wallet = Pubkey::new_unique();
+        gs.dust_threshold_divisor = 10;
+
+        let update = ConfigUpdate {
+            token_reward_rate: Some(5),
+            ..Default::default()
+        };
+        apply_config_update(&mut gs, &update).unwrap();
+        assert_eq!(gs.token_reward_rate, 5);
+
+        // Touching either odds field validates the pair
+        let update = ConfigUpdate {
+            gamble_house_edge_bps: Some(200),
+            ..Default::default()
+        };
+        assert!(apply_config_update(&mut gs.clone(), &update).is_err());
+        let update = ConfigUpdate {
+            gamble_win_chance_bps: Some(5_000),
+            gamble_house_edge_bps: Some(200),
+            ..Default::default()
+        };
+        apply_config_update(&mut gs, &update).unwrap();
+    }
+
+    #[test]
+    fn halving_schedule_is_update_frequency_independent() {
+        let schedule = EmissionSchedule {
+            mode: EMISSION_MODE_HALVING,
+            segment_count: 0,
+            segments: [EmissionSegment::default(); MAX_EMISSION_SEGMENTS],
+            halving_initial_rate: 1_000_000,
+            halving_interval_slots: 10_000,
+            updated_slot: 0,
+        };
+        let end = START + 300_000;
+        let frequent: Vec<u64> = (START + 777..end).step_by(3_331).collect();
+
+        let infrequent = accrue(Some(&schedule), &[], end);
+        let frequent = accrue(Some(&schedule), &frequent, end);
+
+        assert_eq!(infrequent.cumulative_rewards, frequent.cumulative_rewards);
+        // 1M + 500k + 250k + ... per 10k-slot epoch until the rate reaches zero
+        let expected: u64 = (0..20).map(|halvings| (1_000_000u64 >> halvings) * 10_000).sum();
+        assert_eq!(infrequent.cumulative_rewards, expected);
+    }
+
+    #[test]
+    fn catalog_draws_respect_seasons_and_limited_supply() {
+        let horse = |id: u16, flags: u8, supply_left: u16| CatalogHorse {
+            id,
+            speed: 10,
+            stamina_cost: 1,
+            flags,
+            drop_enabled: true,
+            supply_left,
+            name: [0u8; CATALOG_NAME_LEN],
+        };
+        let mut catalog = HorseCatalog {
+            grade_start: [0; HORSE_GRADE_COUNT + 1],
+            droppable_count: [0; HORSE_GRADE_COUNT],
+            season_open: false,
+            entries: Vec::new(),
+        };
+        let ids = |catalog: &HorseCatalog| -> Vec<u16> {
+            catalog.droppable(GRADE_E).iter().map(|horse| horse.id).collect()
+        };
+
+        catalog.insert(GRADE_E, horse(1, 0, 0)).unwrap();
+        catalog.insert(GRADE_E, horse(2, CATALOG_FLAG_SEASONAL, 0)).unwrap();
+        catalog.insert(GRADE_E, horse(3, 0, 0)).unwrap();
+        catalog.insert(GRADE_E, horse(4, CATALOG_FLAG_LIMITED_EDITION, 2)).unwrap();
+        assert_eq!(ids(&catalog), [1, 3, 4]);
+
+        // Opening the season brings seasonal horses back in id order
+        catalog.season_open = true;
+        catalog.partition(GRADE_E);
+        assert_eq!(ids(&catalog), [1, 2, 3, 4]);
+
+        // A limited edition leaves the pool once its supply is drawn
+        catalog.record_drop(GRADE_E, 3);
+        assert_eq!(ids(&catalog), [1, 2, 3, 4]);
+        catalog.record_drop(GRADE_E, 3);
+        assert_eq!(ids(&catalog), [1, 2, 3]);
+        assert_eq!(catalog.entries[3].supply_left, 0);
+    }
+}
@@ -4,10 +4,14 @@ pub mod constants;
 pub mod errors;
 pub mod helpers;
 pub mod instructions;
+pub mod randomness;
+pub mod rng;
 pub mod state;
 
 use errors::HorseGameError;
 use instructions::*;
+use constants::{CATALOG_NAME_LEN, HORSE_GRADE_COUNT};
+use state::{ConfigUpdate, EmissionSegment, NewCatalogHorse, PackTypeConfig, StableTier};
 use std::str::FromStr;
 
 #[cfg(feature = "devnet")]
@@ -50,7 +54,6 @@ pub mod horsegame {
     /// Set or update the token mint (admin only)
     /// Call this after initialize_program to configure the token
     /// Returns the vault address where you need to transfer tokens
-    #[access_control(enforce_admin(ctx.accounts.authority.key))]
     pub fn set_token_mint(ctx: Context<SetTokenMint>) -> Result<()> {
         instructions::set_token_mint(ctx)
     }
@@ -70,18 +73,137 @@ pub mod horsegame {
         instructions::update_pool_manual(ctx)
     }
 
-    pub fn update_parameter(
-        ctx: Context<UpdateParameters>,
-        parameter_index: u8,
-        parameter_value: u64,
+    /// Queue a typed config update; it applies after the timelock
+    pub fn update_config(ctx: Context<UpdateConfig>, update: ConfigUpdate) -> Result<()> {
+        instructions::update_config(ctx, update)
+    }
+
+    /// Drop the queued config update
+    pub fn cancel_parameter_change(ctx: Context<CancelParameterChange>) -> Result<()> {
+        instructions::cancel_parameter_change(ctx)
+    }
+
+    /// Replace the built-in emission stages with segments or a halving curve
+    pub fn set_emission_schedule(
+        ctx: Context<SetEmissionSchedule>,
+        mode: u8,
+        segments: Vec<EmissionSegment>,
+        halving_initial_rate: u64,
+        halving_interval_slots: u64,
+    ) -> Result<()> {
+        instructions::set_emission_schedule(
+            ctx,
+            mode,
+            segments,
+            halving_initial_rate,
+            halving_interval_slots,
+        )
+    }
+
+    /// Record a pool checkpoint every `interval_slots` (0 disables recording)
+    pub fn configure_speed_history(
+        ctx: Context<ConfigureSpeedHistory>,
+        interval_slots: u64,
     ) -> Result<()> {
-        instructions::update_parameter(ctx, parameter_index, parameter_value)
+        instructions::configure_speed_history(ctx, interval_slots)
+    }
+
+    /// Propose a new authority; takes effect once the new key accepts
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        instructions::propose_authority(ctx, new_authority)
+    }
+
+    /// Accept a pending authority proposal (signed by the proposed key)
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        instructions::accept_authority(ctx)
+    }
+
+    /// Replace the stable levels with an on-chain tier table
+    pub fn set_stable_tiers(ctx: Context<SetStableTiers>, tiers: Vec<StableTier>) -> Result<()> {
+        instructions::set_stable_tiers(ctx, tiers)
+    }
+
+    /// Create the on-chain horse catalog from the built-in horse list
+    pub fn init_horse_catalog(ctx: Context<InitHorseCatalog>) -> Result<()> {
+        instructions::init_horse_catalog(ctx)
+    }
+
+    /// Add new horses to the catalog
+    pub fn add_catalog_horses(
+        ctx: Context<EditHorseCatalog>,
+        horses: Vec<NewCatalogHorse>,
+    ) -> Result<()> {
+        instructions::add_catalog_horses(ctx, horses)
+    }
+
+    /// Rename, flag, restock, or retire/restore a catalog horse
+    pub fn update_catalog_horse(
+        ctx: Context<EditHorseCatalog>,
+        horse_id: u16,
+        name: [u8; CATALOG_NAME_LEN],
+        flags: u8,
+        droppable: bool,
+        supply_left: u16,
+    ) -> Result<()> {
+        instructions::update_catalog_horse(ctx, horse_id, name, flags, droppable, supply_left)
+    }
+
+    /// Open or close the season for seasonal catalog horses
+    pub fn set_catalog_season(ctx: Context<EditHorseCatalog>, open: bool) -> Result<()> {
+        instructions::set_catalog_season(ctx, open)
+    }
+
+    /// Create or edit a horse pack type (price, size, grade odds, supply cap)
+    pub fn set_pack_type(
+        ctx: Context<SetPackType>,
+        pack_type: u16,
+        config: PackTypeConfig,
+    ) -> Result<()> {
+        instructions::set_pack_type(ctx, pack_type, config)
+    }
+
+    /// Set pair breeding fees and success odds per grade, and the pairing cooldown
+    pub fn set_breeding_config(
+        ctx: Context<SetBreedingConfig>,
+        pair_fee_microtokens: [u64; HORSE_GRADE_COUNT],
+        pair_success_bps: [u16; HORSE_GRADE_COUNT],
+        pair_cooldown_slots: u64,
+    ) -> Result<()> {
+        instructions::set_breeding_config(
+            ctx,
+            pair_fee_microtokens,
+            pair_success_bps,
+            pair_cooldown_slots,
+        )
+    }
+
+    /// Select SlotHashes or an oracle program as the source of pack and breeding randomness
+    pub fn set_randomness_source(
+        ctx: Context<SetRandomnessSource>,
+        source: u8,
+        oracle_program: Pubkey,
+    ) -> Result<()> {
+        instructions::set_randomness_source(ctx, source, oracle_program)
+    }
+
+    pub fn create_race(
+        ctx: Context<CreateRace>,
+        race_id: u64,
+        entry_close_slot: u64,
+        purse: u64,
+    ) -> Result<()> {
+        instructions::create_race(ctx, race_id, entry_close_slot, purse)
     }
 
     // ────────────────────────────────────────────────────────────────────────────
     ///  NON ADMIN FUNCTIONS
     // ────────────────────────────────────────────────────────────────────────────
     
+    /// Apply the queued config update once its timelock has expired (anyone can crank)
+    pub fn apply_parameter_change(ctx: Context<ApplyParameterChange>) -> Result<()> {
+        instructions::apply_parameter_change(ctx)
+    }
+
     /// Purchase initial stable to start playing
     pub fn purchase_initial_stable(ctx: Context<PurchaseInitialStable>) -> Result<()> {
         instructions::purchase_initial_stable(ctx)
@@ -97,19 +219,42 @@ pub mod horsegame {
         instructions::withdraw_from_race(ctx, horse_index)
     }
 
+    /// Enter several horses into racing with a single reward settlement
+    pub fn enter_race_batch(ctx: Context<EnterRaceBatch>, horse_indices: Vec<u8>) -> Result<()> {
+        instructions::enter_race_batch(ctx, horse_indices)
+    }
+
+    /// Withdraw several horses from racing with a single reward settlement
+    pub fn withdraw_from_race_batch(
+        ctx: Context<WithdrawFromRaceBatch>,
+        horse_indices: Vec<u8>,
+    ) -> Result<()> {
+        instructions::withdraw_from_race_batch(ctx, horse_indices)
+    }
+
+    /// Replace the racing lineup with the speed-maximizing set that fits the stable
+    pub fn optimize_lineup(ctx: Context<OptimizeLineup>) -> Result<()> {
+        instructions::optimize_lineup(ctx)
+    }
+
     /// Release a horse (remove from stable)
     pub fn release_horse(ctx: Context<ReleaseHorse>, horse_index: u8) -> Result<()> {
         instructions::release_horse(ctx, horse_index)
     }
 
-    /// Commit to opening a horse pack
-    pub fn open_horse_pack_commit(ctx: Context<OpenHorsePackCommit>) -> Result<()> {
-        instructions::open_horse_pack_commit(ctx)
+    /// Commit to opening a horse pack (pack type 0 is the built-in pack);
+    /// `secret_hash` is the sha256 of a secret revealed at settle
+    pub fn open_horse_pack_commit(
+        ctx: Context<OpenHorsePackCommit>,
+        pack_type: u16,
+        secret_hash: [u8; 32],
+    ) -> Result<()> {
+        instructions::open_horse_pack_commit(ctx, pack_type, secret_hash)
     }
 
     /// Settle horse pack opening (reveal horses)
-    pub fn settle_open_horse_pack(ctx: Context<SettleOpenHorsePack>) -> Result<()> {
-        instructions::settle_open_horse_pack(ctx)
+    pub fn settle_open_horse_pack(ctx: Context<SettleOpenHorsePack>, secret: [u8; 32]) -> Result<()> {
+        instructions::settle_open_horse_pack(ctx, secret)
     }
 
     /// Upgrade stable capacity
@@ -122,25 +267,128 @@ pub mod horsegame {
         instructions::claim_rewards(ctx)
     }
 
-    /// Commit to breeding horses
+    /// Commit to fusing 2-5 horses of one grade into a next-grade roll
     pub fn breed_horses_commit(
         ctx: Context<BreedHorsesCommit>,
         horse_indices: Vec<u8>,
+        secret_hash: [u8; 32],
     ) -> Result<()> {
-        instructions::breed_horses_commit(ctx, horse_indices)
+        instructions::breed_horses_commit(ctx, horse_indices, secret_hash)
     }
 
     /// Settle breeding (reveal offspring)
-    pub fn breed_horses_settle(ctx: Context<BreedHorsesSettle>) -> Result<()> {
-        instructions::breed_horses_settle(ctx)
+    pub fn breed_horses_settle(ctx: Context<BreedHorsesSettle>, secret: [u8; 32]) -> Result<()> {
+        instructions::breed_horses_settle(ctx, secret)
+    }
+
+    /// Start breeding two parents into one foal (commit phase)
+    pub fn breed_pair_commit(
+        ctx: Context<BreedPairCommit>,
+        sire_index: u8,
+        dam_index: u8,
+        secret_hash: [u8; 32],
+    ) -> Result<()> {
+        instructions::breed_pair_commit(ctx, sire_index, dam_index, secret_hash)
+    }
+
+    /// Settle pair breeding (reveal the foal)
+    pub fn breed_pair_settle(ctx: Context<BreedPairSettle>, secret: [u8; 32]) -> Result<()> {
+        instructions::breed_pair_settle(ctx, secret)
+    }
+
+    /// Turn a non-racing horse into a Token-2022 NFT
+    pub fn mint_horse_nft(ctx: Context<MintHorseNft>, horse_index: u8) -> Result<()> {
+        instructions::mint_horse_nft(ctx, horse_index)
+    }
+
+    /// Burn a horse NFT and return the horse to the player's stable
+    pub fn deposit_horse_nft(ctx: Context<DepositHorseNft>) -> Result<()> {
+        instructions::deposit_horse_nft(ctx)
+    }
+
+    /// Give a non-racing horse to another player
+    pub fn transfer_horse(ctx: Context<TransferHorse>, horse_index: u8) -> Result<()> {
+        instructions::transfer_horse(ctx, horse_index)
+    }
+
+    /// List a horse for sale, escrowing it in a listing account
+    pub fn list_horse(ctx: Context<ListHorse>, horse_index: u8, price: u64) -> Result<()> {
+        instructions::list_horse(ctx, horse_index, price)
+    }
+
+    /// Cancel a listing and return the horse to the seller
+    pub fn cancel_listing(ctx: Context<CancelListing>) -> Result<()> {
+        instructions::cancel_listing(ctx)
+    }
+
+    /// Buy a listed horse
+    pub fn buy_horse(ctx: Context<BuyHorse>) -> Result<()> {
+        instructions::buy_horse(ctx)
+    }
+
+    /// Commit to a gamble of `amount` tokens
+    pub fn gamble_commit(ctx: Context<GambleCommit>, amount: u64) -> Result<()> {
+        instructions::gamble_commit(ctx, amount)
+    }
+
+    /// Settle a gamble (reveal outcome)
+    pub fn gamble_settle(ctx: Context<GambleSettle>) -> Result<()> {
+        instructions::gamble_settle(ctx)
+    }
+
+    /// Register a horse for a race event during its entry window
+    pub fn register_for_race(ctx: Context<RegisterForRace>, horse_index: u8) -> Result<()> {
+        instructions::register_for_race(ctx, horse_index)
+    }
+
+    /// Settle a race event once entries close (permissionless)
+    pub fn settle_race(ctx: Context<SettleRace>) -> Result<()> {
+        instructions::settle_race(ctx)
+    }
+
+    /// Cancel a race whose draw slot has left the SlotHashes sysvar (permissionless)
+    pub fn cancel_race(ctx: Context<CancelRace>) -> Result<()> {
+        instructions::cancel_race(ctx)
+    }
+
+    /// Claim a podium prize from a settled race event
+    pub fn claim_race_prize(ctx: Context<ClaimRacePrize>) -> Result<()> {
+        instructions::claim_race_prize(ctx)
     }
 
     /// Cancel a pending action
     pub fn cancel_pending_action(ctx: Context<CancelPendingAction>) -> Result<()> {
         instructions::cancel_pending_action(ctx)
     }
+
+    /// Stake tokens into the staking vault
+    pub fn stake_tokens(ctx: Context<StakeTokens>, amount: u64) -> Result<()> {
+        instructions::stake_tokens(ctx, amount)
+    }
+
+    /// Unstake tokens once the lockup has expired
+    pub fn unstake_tokens(ctx: Context<UnstakeTokens>, amount: u64) -> Result<()> {
+        instructions::unstake_tokens(ctx, amount)
+    }
+
+    /// Claim token rewards earned by staked tokens
+    pub fn claim_staking_rewards(ctx: Context<ClaimStakingRewards>) -> Result<()> {
+        instructions::claim_staking_rewards(ctx)
+    }
+
+    /// Deposit SOL into the stakers' SOL rewards wallet (permissionless)
+    pub fn deposit_sol_rewards(ctx: Context<DepositSolRewards>, amount: u64) -> Result<()> {
+        instructions::deposit_sol_rewards(ctx, amount)
+    }
+
+    /// Claim SOL rewards earned by staked tokens
+    pub fn claim_sol_rewards(ctx: Context<ClaimSolRewards>) -> Result<()> {
+        instructions::claim_sol_rewards(ctx)
+    }
 }
 
+/// Only gates `initialize_program`, before `GlobalState::authority` exists;
+/// every later admin check is `has_one = authority` on the global state
 fn enforce_admin(key: &Pubkey) -> Result<()> {
     #[cfg(not(feature = "test"))]
     require!(
@@ -0,0 +1,241 @@
+//! Where commit-reveal settles get their randomness.
+//!
+//! Pack and breeding settles ask a `RandomnessSource` for the value belonging
+//! to a commit and never parse the underlying account themselves. The source
+//! is chosen by the authority (`GlobalState::randomness_source`), and it and
+//! the oracle's `RandomnessOracles` index are recorded in the player at commit,
+//! so switching sources or oracles never strands a pending action.
+//!
+//! Gambles and races stay on SlotHashes: their cancel paths depend on whether
+//! the reveal slot was skipped or has expired from the sysvar.
+
+use crate::{constants::*, errors::HorseGameError, helpers::*};
+use anchor_lang::prelude::*;
+
+/// Randomness for one commit and the slot it became known
+pub struct Reveal {
+    pub slot: u64,
+    pub value: [u8; 32],
+}
+
+pub trait RandomnessSource {
+    /// Randomness for an action committed at `commit_slot`, which nobody could
+    /// know at that slot; `current_slot` is the settle slot
+    fn reveal(&self, commit_slot: u64, current_slot: u64) -> Result<Reveal>;
+}
+
+/// The SlotHashes entry `MIN_RANDOMNESS_DELAY_SLOTS` after the commit
+pub struct SlotHashesRandomness<'a, 'info> {
+    pub sysvar: &'a AccountInfo<'info>,
+}
+
+impl RandomnessSource for SlotHashesRandomness<'_, '_> {
+    fn reveal(&self, commit_slot: u64, current_slot: u64) -> Result<Reveal> {
+        let reveal_slot = commit_slot + MIN_RANDOMNESS_DELAY_SLOTS;
+        require!(
+            current_slot >= reveal_slot,
+            HorseGameError::RandomnessNotResolved
+        );
+
+        match lookup_slot_hash(self.sysvar, reveal_slot)? {
+            SlotHashLookup::Found(value) => Ok(Reveal {
+                slot: reveal_slot,
+                value,
+            }),
+            _ => err!(HorseGameError::SlotNotFound),
+        }
+    }
+}
+
+/// A fulfilled request account of the configured oracle program. The account
+/// must be the oracle's PDA `[ORACLE_REQUEST_SEED, player, commit_slot]`, so
+/// each commit has exactly one request and the player cannot shop for values
+pub struct OracleRandomness<'a, 'info> {
+    pub request: &'a AccountInfo<'info>,
+    pub oracle_program: Pubkey,
+    pub player: Pubkey,
+}
+
+impl RandomnessSource for OracleRandomness<'_, '_> {
+    fn reveal(&self, commit_slot: u64, _current_slot: u64) -> Result<Reveal> {
+        require!(
+            self.request.owner == &self.oracle_program,
+            HorseGameError::InvalidRandomnessOracle
+        );
+        let (expected, _) = Pubkey::find_program_address(
+            &[
+                ORACLE_REQUEST_SEED,
+                self.player.as_ref(),
+                &commit_slot.to_le_bytes(),
+            ],
+            &self.oracle_program,
+        );
+        require!(
+            self.request.key == &expected,
+            HorseGameError::InvalidRandomnessOracle
+        );
+
+        let data = self.request.try_borrow_data()?;
+        require!(
+            data.len() >= ORACLE_REQUEST_LEN,
+            HorseGameError::InvalidRandomnessOracle
+        );
+        let fulfilled_slot = u64::from_le_bytes(
+            data[ORACLE_FULFILLED_SLOT_OFFSET..ORACLE_FULFILLED_SLOT_OFFSET + 8]
+                .try_into()
+                .unwrap(),
+        );
+        require!(
+            fulfilled_slot > commit_slot,
+            HorseGameError::RandomnessNotResolved
+        );
+
+        let mut value = [0u8; 32];
+        value.copy_from_slice(&data[ORACLE_VALUE_OFFSET..ORACLE_VALUE_OFFSET + 32]);
+        Ok(Reveal {
+            slot: fulfilled_slot,
+            value,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anchor_lang::solana_program::sysvar::slot_hashes;
+
+    const COMMIT_SLOT: u64 = 1_000;
+
+    fn mock_oracle() -> Pubkey {
+        Pubkey::new_from_array([7; 32])
+    }
+
+    fn player() -> Pubkey {
+        Pubkey::new_from_array([9; 32])
+    }
+
+    fn request_key(commit_slot: u64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                ORACLE_REQUEST_SEED,
+                player().as_ref(),
+                &commit_slot.to_le_bytes(),
+            ],
+            &mock_oracle(),
+        )
+        .0
+    }
+
+    fn request_data(fulfilled_slot: u64) -> Vec<u8> {
+        let mut data = vec![0u8; ORACLE_REQUEST_LEN];
+        data[ORACLE_FULFILLED_SLOT_OFFSET..ORACLE_FULFILLED_SLOT_OFFSET + 8]
+            .copy_from_slice(&fulfilled_slot.to_le_bytes());
+        data[ORACLE_VALUE_OFFSET..ORACLE_VALUE_OFFSET + 32].copy_from_slice(&[42; 32]);
+        data
+    }
+
+    fn reveal_from_oracle(key: Pubkey, owner: Pubkey, mut data: Vec<u8>) -> Result<Reveal> {
+        let mut lamports = 0;
+        let request = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        OracleRandomness {
+            request: &request,
+            oracle_program: mock_oracle(),
+            player: player(),
+        }
+        .reveal(COMMIT_SLOT, COMMIT_SLOT + 10)
+    }
+
+    #[test]
+    fn oracle_reveals_a_fulfilled_request() {
+        let reveal = reveal_from_oracle(
+            request_key(COMMIT_SLOT),
+            mock_oracle(),
+            request_data(COMMIT_SLOT + 3),
+        )
+        .unwrap();
+        assert_eq!(reveal.slot, COMMIT_SLOT + 3);
+        assert_eq!(reveal.value, [42; 32]);
+    }
+
+    #[test]
+    fn oracle_rejects_accounts_of_other_programs() {
+        let result = reveal_from_oracle(
+            request_key(COMMIT_SLOT),
+            Pubkey::default(),
+            request_data(COMMIT_SLOT + 3),
+        );
+        assert_eq!(
+            result.err(),
+            Some(HorseGameError::InvalidRandomnessOracle.into())
+        );
+    }
+
+    #[test]
+    fn oracle_rejects_requests_of_other_commits() {
+        let result = reveal_from_oracle(
+            request_key(COMMIT_SLOT + 1),
+            mock_oracle(),
+            request_data(COMMIT_SLOT + 3),
+        );
+        assert_eq!(
+            result.err(),
+            Some(HorseGameError::InvalidRandomnessOracle.into())
+        );
+    }
+
+    #[test]
+    fn oracle_waits_until_fulfilled_after_the_commit() {
+        for fulfilled_slot in [0, COMMIT_SLOT] {
+            let result = reveal_from_oracle(
+                request_key(COMMIT_SLOT),
+                mock_oracle(),
+                request_data(fulfilled_slot),
+            );
+            assert_eq!(
+                result.err(),
+                Some(HorseGameError::RandomnessNotResolved.into())
+            );
+        }
+    }
+
+    #[test]
+    fn slot_hashes_reveal_the_delayed_slot() {
+        let reveal_slot = COMMIT_SLOT + MIN_RANDOMNESS_DELAY_SLOTS;
+        let mut data = 2u64.to_le_bytes().to_vec();
+        for (slot, hash) in [(reveal_slot + 1, [1u8; 32]), (reveal_slot, [2u8; 32])] {
+            data.extend_from_slice(&slot.to_le_bytes());
+            data.extend_from_slice(&hash);
+        }
+        let key = slot_hashes::id();
+        let owner = Pubkey::default();
+        let mut lamports = 0;
+        let sysvar = AccountInfo::new(
+            &key,
+            false,
+            false,
+            &mut lamports,
+            &mut data,
+            &owner,
+            false,
+            0,
+        );
+        let source = SlotHashesRandomness { sysvar: &sysvar };
+
+        let reveal = source.reveal(COMMIT_SLOT, reveal_slot + 1).unwrap();
+        assert_eq!(reveal.slot, reveal_slot);
+        assert_eq!(reveal.value, [2; 32]);
+        assert_eq!(
+            source.reveal(COMMIT_SLOT, reveal_slot - 1).err(),
+            Some(HorseGameError::RandomnessNotResolved.into())
+        );
+    }
+}
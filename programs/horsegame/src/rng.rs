@@ -0,0 +1,238 @@
+//! Deterministic random stream for commit-reveal settles.
+//!
+//! Block `n` of a stream is `sha256(seed || player || n as u64 LE)` and is
+//! consumed four bytes at a time, so every draw is independent of the others.
+//! Reveals seed it with `sha256(slot_hash || secret)`, where the player
+//! committed to `sha256(secret)` before the slot hash existed, so clients can
+//! replay a settle from its slot hash, secret and player account.
+
+use crate::errors::HorseGameError;
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::hash::hashv;
+
+/// Commitment a player stores at commit time for the secret revealed at settle
+pub fn entropy_commitment(secret: &[u8; 32]) -> [u8; 32] {
+    hashv(&[secret]).to_bytes()
+}
+
+/// Secret a settle mixes into its stream. Actions committed before secrets
+/// existed hold a zeroed commitment (new commits must be non-zero) and settle
+/// with the zero secret whatever is supplied, so the settler still can't pick
+/// an outcome
+pub fn revealed_secret(commitment: &[u8; 32], secret: &[u8; 32]) -> Result<[u8; 32]> {
+    if *commitment == [0; 32] {
+        return Ok([0; 32]);
+    }
+    require!(
+        entropy_commitment(secret) == *commitment,
+        HorseGameError::InvalidEntropySecret
+    );
+    Ok(*secret)
+}
+
+pub struct RandomStream {
+    seed: [u8; 32],
+    player: Pubkey,
+    counter: u64,
+    block: [u8; 32],
+    offset: usize,
+}
+
+impl RandomStream {
+    pub fn new(seed: [u8; 32], player: &Pubkey) -> Self {
+        Self {
+            seed,
+            player: *player,
+            counter: 0,
+            block: [0; 32],
+            offset: 32,
+        }
+    }
+
+    /// Stream for a reveal: neither the leader (slot hash) nor the player
+    /// (secret) alone controls it
+    pub fn with_secret(slot_hash: &[u8; 32], secret: &[u8; 32], player: &Pubkey) -> Self {
+        Self::new(hashv(&[slot_hash, secret]).to_bytes(), player)
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        if self.offset == 32 {
+            self.block = hashv(&[
+                &self.seed,
+                self.player.as_ref(),
+                &self.counter.to_le_bytes(),
+            ])
+            .to_bytes();
+            self.counter += 1;
+            self.offset = 0;
+        }
+        let value =
+            u32::from_le_bytes(self.block[self.offset..self.offset + 4].try_into().unwrap());
+        self.offset += 4;
+        value
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let low = self.next_u32() as u64;
+        let high = self.next_u32() as u64;
+        (high << 32) | low
+    }
+
+    /// Uniform value in `0..bound` by multiply-shift, so the bias is at most
+    /// `bound / 2^64`; `bound` must be non-zero
+    pub fn below(&mut self, bound: u64) -> u64 {
+        ((self.next_u64() as u128 * bound as u128) >> 64) as u64
+    }
+
+    /// True with probability `bps / 10_000`
+    pub fn chance_bps(&mut self, bps: u32) -> bool {
+        self.below(10_000) < bps as u64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SEEDS: u64 = 2_000;
+
+    fn seed(i: u64) -> [u8; 32] {
+        hashv(&[b"seed", &i.to_le_bytes()]).to_bytes()
+    }
+
+    fn stream(i: u64) -> RandomStream {
+        RandomStream::new(seed(i), &Pubkey::new_from_array(seed(i + SEEDS)))
+    }
+
+    fn chi_square(counts: &[u64], total: u64) -> f64 {
+        let expected = total as f64 / counts.len() as f64;
+        counts
+            .iter()
+            .map(|&count| (count as f64 - expected).powi(2) / expected)
+            .sum()
+    }
+
+    #[test]
+    fn same_seed_and_player_replay_the_same_stream() {
+        let mut a = stream(7);
+        let mut b = stream(7);
+        for _ in 0..100 {
+            assert_eq!(a.next_u32(), b.next_u32());
+        }
+    }
+
+    #[test]
+    fn player_key_changes_the_stream() {
+        let mut a = RandomStream::new(seed(1), &Pubkey::new_from_array(seed(2)));
+        let mut b = RandomStream::new(seed(1), &Pubkey::new_from_array(seed(3)));
+        let a: Vec<u32> = (0..16).map(|_| a.next_u32()).collect();
+        let b: Vec<u32> = (0..16).map(|_| b.next_u32()).collect();
+        assert_ne!(a, b);
+    }
+
+    #[test]
+    fn secret_changes_the_stream() {
+        let player = Pubkey::new_from_array(seed(9));
+        let mut a = RandomStream::with_secret(&seed(1), &seed(2), &player);
+        let mut b = RandomStream::with_secret(&seed(1), &seed(3), &player);
+        let a: Vec<u32> = (0..16).map(|_| a.next_u32()).collect();
+        let b: Vec<u32> = (0..16).map(|_| b.next_u32()).collect();
+        assert_ne!(a, b);
+        assert_ne!(entropy_commitment(&seed(2)), seed(2));
+    }
+
+    #[test]
+    fn legacy_commits_settle_with_the_zero_secret() {
+        let secret = seed(4);
+        let commitment = entropy_commitment(&secret);
+        assert_eq!(revealed_secret(&commitment, &secret).unwrap(), secret);
+        assert_eq!(
+            revealed_secret(&commitment, &seed(5)).err(),
+            Some(HorseGameError::InvalidEntropySecret.into())
+        );
+        assert_eq!(revealed_secret(&[0; 32], &seed(5)).unwrap(), [0; 32]);
+    }
+
+    #[test]
+    fn draws_do_not_repeat_across_blocks() {
+        // Many blocks of draws; a full pack settle already spans several
+        for i in 0..100 {
+            let mut rng = stream(i);
+            let mut draws: Vec<u32> = (0..256).map(|_| rng.next_u32()).collect();
+            draws.sort_unstable();
+            draws.dedup();
+            assert_eq!(draws.len(), 256);
+        }
+    }
+
+    #[test]
+    fn below_is_uniform_over_many_seeds() {
+        let mut counts = [0u64; 10];
+        for i in 0..SEEDS {
+            let mut rng = stream(i);
+            for _ in 0..50 {
+                counts[rng.below(10) as usize] += 1;
+            }
+        }
+        // 9 degrees of freedom: p = 0.001 at 27.88
+        assert!(chi_square(&counts, SEEDS * 50) < 27.88, "{counts:?}");
+    }
+
+    #[test]
+    fn consecutive_draws_are_independent() {
+        let mut counts = [0u64; 64];
+        for i in 0..SEEDS {
+            let mut rng = stream(i);
+            for _ in 0..25 {
+                let first = rng.below(8);
+                let second = rng.below(8);
+                counts[(first * 8 + second) as usize] += 1;
+            }
+        }
+        // 63 degrees of freedom: p = 0.001 at 103.44
+        assert!(chi_square(&counts, SEEDS * 25) < 103.44);
+    }
+
+    #[test]
+    fn bits_are_balanced() {
+        let mut set = [0u64; 32];
+        let draws = SEEDS * 25;
+        for i in 0..SEEDS {
+            let mut rng = stream(i);
+            for _ in 0..25 {
+                let value = rng.next_u32();
+                for (bit, count) in set.iter_mut().enumerate() {
+                    *count += (value >> bit) as u64 & 1;
+                }
+            }
+        }
+        // Within 4 standard deviations of half the draws
+        let tolerance = 4.0 * (draws as f64 * 0.25).sqrt();
+        for (bit, &count) in set.iter().enumerate() {
+            assert!(
+                (count as f64 - draws as f64 / 2.0).abs() < tolerance,
+                "bit {bit}: {count}"
+            );
+        }
+    }
+
+    #[test]
+    fn chance_bps_matches_its_odds() {
+        for bps in [100u32, 2_000, 5_000, 9_000] {
+            let mut hits = 0u64;
+            for i in 0..SEEDS {
+                let mut rng = stream(i);
+                hits += (0..10).filter(|_| rng.chance_bps(bps)).count() as u64;
+            }
+            let trials = (SEEDS * 10) as f64;
+            let p = bps as f64 / 10_000.0;
+            let tolerance = 4.0 * (trials * p * (1.0 - p)).sqrt();
+            assert!(
+                (hits as f64 - trials * p).abs() < tolerance,
+                "{bps} bps: {hits} hits"
+            );
+        }
+        assert!(!stream(0).chance_bps(0));
+        assert!(stream(0).chance_bps(10_000));
+    }
+}
@@ -1,4 +1,5 @@
 use crate::constants::*;
+use crate::helpers::{halving_emission, halving_rate_at, segment_emission, segment_rate_at};
 use crate::HorseGameError;
 use anchor_lang::prelude::*;
 
@@ -52,22 +53,77 @@ pub struct GlobalState {
     pub last_staking_reward_slot: u64,
     pub token_reward_rate: u64,
     pub total_sol_deposited: u64,
+    pub sol_rewards_share: u8,       // % of SOL fees routed to stakers
+
+    /* ── gamble odds ────────────────────────────── */
+    pub gamble_win_chance_bps: u16, // Probability of winning a gamble (basis points)
+    pub gamble_house_edge_bps: u16, // Edge kept by the bankroll on winning payouts (basis points)
+
+    /* ── marketplace ────────────────────────────── */
+    pub marketplace_fee_bps: u16, // Fee on horse sales, split by `burn_rate` (basis points)
+
+    /* ── emission schedule ──────────────────────── */
+    pub emission_schedule_enabled: bool, // Use `EmissionSchedule` instead of the built-in stages
+
+    /* ── analytics ──────────────────────────────── */
+    pub speed_history_enabled: bool, // `update_pool` records into `SpeedHistory`
+
+    /* ── stables ────────────────────────────────── */
+    pub stable_tiers_enabled: bool, // Read levels from `StableTierConfig` instead of STABLE_CONFIGS
+
+    /* ── horse catalog ──────────────────────────── */
+    pub horse_catalog_enabled: bool, // Draw horses from `HorseCatalog` instead of HORSE_DATA
+
+    /* ── pack pity ──────────────────────────────── */
+    pub pity_b_threshold: u16,  // From this roll without an SS, rolls are at least B (0 = off)
+    pub pity_ss_threshold: u16, // This roll without an SS is guaranteed SS (0 = off)
+
+    /* ── randomness ─────────────────────────────── */
+    pub randomness_source: u8,       // RANDOMNESS_SOURCE_* used by new pack and breeding commits
+    pub randomness_oracle_index: u8, // `RandomnessOracles` entry used by new oracle commits
+
+    /* ── gamble exposure ────────────────────────── */
+    pub gamble_liability: u64, // Win payouts reserved by pending gambles
+
+    /* ── race purses ────────────────────────────── */
+    pub race_purses_reserved: u64, // Unpaid purses of open races, held back from emissions
 
     /* ── future expansion ───────────────────────── */
-    pub padding: [u8; 64],
+    pub padding: [u8; 31],
+}
+
+/// Program-owned lamport pool paid out to stakers via `acc_sol_rewards_per_token`
+#[account]
+pub struct SolRewardsWallet {
+    pub total_claimed: u64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
 pub enum PendingRandomAction {
     None,
+    /// Gamble whose win payout is reserved in `GlobalState::gamble_liability`
     Gamble {
         amount: u64,
+        payout: u64,         // Paid on a win
+        win_chance_bps: u16, // Odds as configured at commit
     },
     HorsePack,
     Breeding {
         horse_indices: [u8; 128],
         horse_count: u8,
     },
+    PairBreeding {
+        sire_index: u8,
+        dam_index: u8,
+        success_bps: u16, // Odds as configured at commit
+        foal_id: u16,     // Id reserved for the foal
+    },
+    /// Pack of `pack_type`, as configured at commit
+    PackOpening {
+        pack_type: u16,
+        horse_count: u8,
+        grade_weights: [u16; HORSE_GRADE_COUNT],
+    },
 }
 
 impl Default for PendingRandomAction {
@@ -76,6 +132,24 @@ impl Default for PendingRandomAction {
     }
 }
 
+impl PendingRandomAction {
+    /// Pack type of a pending pack; a legacy `HorsePack` is the built-in one
+    pub fn pack_type(&self) -> u16 {
+        match self {
+            PendingRandomAction::PackOpening { pack_type, .. } => *pack_type,
+            _ => DEFAULT_PACK_TYPE_ID,
+        }
+    }
+
+    /// Foal id reserved by a pending `PairBreeding`, 0 otherwise
+    pub fn foal_id(&self) -> u16 {
+        match self {
+            PendingRandomAction::PairBreeding { foal_id, .. } => *foal_id,
+            _ => 0,
+        }
+    }
+}
+
 #[account]
 pub struct Player {
     pub owner: Pubkey,
@@ -115,8 +189,25 @@ pub struct Player {
     pub total_races_entered: u64,
     pub total_race_wins: u64,
 
-    /* ── future expansion ───────────────────────── */
-    pub padding: [u8; 64],
+    /* ── marketplace ────────────────────────────── */
+    pub listings_created: u32, // Nonce for this player's listing PDAs
+
+    /* ── horse packs ────────────────────────────── */
+    pub pity_counter: u16, // Pack rolls since the last SS
+
+    /* ── SOL carry ──────────────────────────────── */
+    pub unpaid_sol_rewards: u64, // Accrued SOL rewards the wallet could not cover yet
+
+    /* ── commit-reveal ──────────────────────────── */
+    pub entropy_commitment: [u8; 32], // sha256 of the secret revealed at settle
+    pub pending_randomness_source: u8, // Source chosen at commit, used at settle
+    pub pending_oracle_index: u8,      // `RandomnessOracles` entry chosen at commit
+
+    /* ── staking carry ──────────────────────────── */
+    pub unpaid_staking_rewards: u64, // Accrued staking rewards the vault could not cover yet
+
+    /* ── race events ────────────────────────────── */
+    pub race_entry_lock_slot: u64, // No horse leaves the stable before the latest entry close of a race joined
 }
 
 impl Player {
@@ -130,6 +221,22 @@ impl Player {
         Ok(())
     }
 
+    /// A pending pack or breeding settle counts on the stable space it left
+    /// free, so no horse may arrive until it is settled or cancelled
+    pub fn can_receive_horse(&self) -> bool {
+        self.pending_action == PendingRandomAction::None
+    }
+
+    /// A registered horse must stay put until its race's entries close, or a
+    /// new owner could register it again under another wallet
+    pub fn require_horses_unlocked(&self, slot: u64) -> Result<()> {
+        require!(
+            slot >= self.race_entry_lock_slot,
+            HorseGameError::HorsesLockedForRace
+        );
+        Ok(())
+    }
+
     pub fn batch_remove_horses(&mut self, indices: &[u8]) -> Result<()> {
         let mut new_horses = Vec::with_capacity(self.horse_count as usize);
         let mut new_bitset = 0u128;
@@ -209,6 +316,203 @@ pub struct Stable {
     pub feed_capacity: u64,  // Total feed capacity
 }
 
+/// Authority-edited stable levels; replaces `STABLE_CONFIGS` once
+/// `stable_tiers_enabled` is set
+#[account]
+pub struct StableTierConfig {
+    pub tier_count: u8,
+    pub tiers: [StableTier; MAX_STABLE_TIERS],
+}
+
+impl StableTierConfig {
+    pub fn tier(&self, level: u8) -> Option<StableTier> {
+        self.tiers[..self.tier_count as usize].get(level as usize).copied()
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct StableTier {
+    pub racing_slots: u8,
+    pub feed_capacity: u64,
+    pub cost: u64, // In microtokens
+}
+
+/// A purchasable horse pack; id 0 is reserved for the built-in pack
+#[account]
+pub struct PackType {
+    pub id: u16,
+    pub price_microtokens: u64,
+    pub sol_price_lamports: Option<u64>, // Charged on top of the token price
+    pub horse_count: u8,
+    pub grade_weights: [u16; HORSE_GRADE_COUNT], // Relative odds indexed by grade, E..SS
+    pub supply_cap: Option<u64>,                 // Max packs ever opened (limited drops)
+    pub opened: u64,
+    pub active: bool,
+}
+
+/// Argument to `set_pack_type`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PackTypeConfig {
+    pub price_microtokens: u64,
+    pub sol_price_lamports: Option<u64>,
+    pub horse_count: u8,
+    pub grade_weights: [u16; HORSE_GRADE_COUNT],
+    pub supply_cap: Option<u64>,
+    pub active: bool,
+}
+
+/// Per-grade fee and odds for `breed_pair_commit`, indexed by the foal's grade
+#[account]
+pub struct BreedingConfig {
+    pub pair_fee_microtokens: [u64; HORSE_GRADE_COUNT],
+    pub pair_success_bps: [u16; HORSE_GRADE_COUNT],
+    pub foal_ids_issued: u16, // Foal ids handed out from FOAL_ID_START so far
+    pub pair_cooldown_slots: u64, // Slots a stable waits between pairings
+}
+
+impl BreedingConfig {
+    /// Hands out the next foal id, failing once all FOAL_ID_CAPACITY are used
+    pub fn reserve_foal_id(&mut self) -> Result<u16> {
+        require!(
+            self.foal_ids_issued < FOAL_ID_CAPACITY,
+            HorseGameError::FoalIdsExhausted
+        );
+        let foal_id = FOAL_ID_START + self.foal_ids_issued;
+        self.foal_ids_issued += 1;
+        Ok(foal_id)
+    }
+}
+
+/// Pair breeding record of a stable at `[BREEDER_SEED, player]`. Parents stay
+/// in the stable, so pairings are rate limited or a pair would copy itself
+/// for the fee
+#[account]
+pub struct Breeder {
+    pub last_pair_commit_slot: u64, // 0 until the first pairing
+}
+
+/// Lineage of a pair-bred foal at `[FOAL_RECORD_SEED, foal id]`. Every foal
+/// gets its own `Horse::id`, so the record follows it through transfers,
+/// sales and NFT mints
+#[account]
+pub struct FoalRecord {
+    pub foal: Horse,   // As born
+    pub look_id: u16,  // Id of the parent whose look it took; a foal id leads to that parent's record
+    pub sire: Horse,   // Parents as they were when the foal was born
+    pub dam: Horse,
+    pub breeder: Pubkey,
+    pub bred_slot: u64,
+}
+
+/// Oracle programs the authority has configured, in the order they were first
+/// set. Entries are never removed or reordered, so a pending commit can always
+/// find the oracle it was made under by its index
+#[account]
+pub struct RandomnessOracles {
+    pub programs: Vec<Pubkey>, // At most MAX_RANDOMNESS_ORACLES
+}
+
+/// Horses that packs and breeding can award, grouped by grade: grade `g`
+/// spans `entries[grade_start[g]..grade_start[g + 1]]` with its
+/// `droppable_count[g]` droppable horses first, so a draw is a single index.
+/// Both sections are kept in id order, so a horse retired and restored draws
+/// from the same slot as before
+#[account]
+pub struct HorseCatalog {
+    pub grade_start: [u16; HORSE_GRADE_COUNT + 1],
+    pub droppable_count: [u16; HORSE_GRADE_COUNT],
+    pub season_open: bool, // Seasonal horses only drop while set
+    pub entries: Vec<CatalogHorse>, // At most MAX_CATALOG_HORSES
+}
+
+impl HorseCatalog {
+    pub fn droppable(&self, grade: u8) -> &[CatalogHorse] {
+        let start = self.grade_start[grade as usize] as usize;
+        &self.entries[start..start + self.droppable_count[grade as usize] as usize]
+    }
+
+    /// Grade and entry index of `horse_id`
+    pub fn find(&self, horse_id: u16) -> Option<(u8, usize)> {
+        let index = self.entries.iter().position(|horse| horse.id == horse_id)?;
+        let grade = (0..HORSE_GRADE_COUNT)
+            .find(|&grade| index < self.grade_start[grade + 1] as usize)?;
+        Some((grade as u8, index))
+    }
+
+    /// Whether `horse` may drop now: enabled by the authority, in season if
+    /// seasonal and with supply left if a limited edition
+    pub fn can_drop(&self, horse: &CatalogHorse) -> bool {
+        horse.drop_enabled
+            && (horse.flags & CATALOG_FLAG_SEASONAL == 0 || self.season_open)
+            && (horse.flags & CATALOG_FLAG_LIMITED_EDITION == 0 || horse.supply_left > 0)
+    }
+
+    /// Appends a horse to its grade, then re-sorts the grade's sections
+    pub fn insert(&mut self, grade: u8, horse: CatalogHorse) -> Result<()> {
+        require!(
+            self.entries.len() < MAX_CATALOG_HORSES,
+            HorseGameError::HorseCatalogFull
+        );
+
+        let grade = grade as usize;
+        self.entries.insert(self.grade_start[grade + 1] as usize, horse);
+        for start in &mut self.grade_start[grade + 1..] {
+            *start += 1;
+        }
+        self.partition(grade as u8);
+        Ok(())
+    }
+
+    /// Moves the grade's droppable horses to the front of its span, each
+    /// section in id order
+    pub fn partition(&mut self, grade: u8) {
+        let grade = grade as usize;
+        let span = self.grade_start[grade] as usize..self.grade_start[grade + 1] as usize;
+        self.entries[span.clone()].sort_by_key(|horse| horse.id);
+        let (mut droppable, held): (Vec<CatalogHorse>, Vec<CatalogHorse>) =
+            self.entries[span.clone()].iter().partition(|horse| self.can_drop(horse));
+        self.droppable_count[grade] = droppable.len() as u16;
+        droppable.extend(held);
+        self.entries[span].copy_from_slice(&droppable);
+    }
+
+    /// Counts a drop of `droppable(grade)[picked]`, retiring a limited
+    /// edition from drops once its supply runs out
+    pub fn record_drop(&mut self, grade: u8, picked: usize) {
+        let index = self.grade_start[grade as usize] as usize + picked;
+        let horse = &mut self.entries[index];
+        if horse.flags & CATALOG_FLAG_LIMITED_EDITION != 0 {
+            horse.supply_left = horse.supply_left.saturating_sub(1);
+            if horse.supply_left == 0 {
+                self.partition(grade);
+            }
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct CatalogHorse {
+    pub id: u16,
+    pub speed: u16,
+    pub stamina_cost: u8,
+    pub flags: u8, // CATALOG_FLAG_*
+    pub drop_enabled: bool, // Authority setting; season and supply can still hold it back
+    pub supply_left: u16,   // Drops left for a limited edition
+    pub name: [u8; CATALOG_NAME_LEN], // UTF-8, zero padded
+}
+
+/// Argument to `add_catalog_horses`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct NewCatalogHorse {
+    pub id: u16,
+    pub grade: u8,
+    pub speed: u16,
+    pub stamina_cost: u8,
+    pub flags: u8,
+    pub supply: u16, // Drop cap for a limited edition
+    pub name: [u8; CATALOG_NAME_LEN],
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
 pub struct Horse {
     pub id: u16,            // Horse ID
@@ -217,10 +521,188 @@ pub struct Horse {
     pub stamina_cost: u8,   // Feed consumption per slot
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+/// Escrow record for a horse that left its `Player` account as a Token-2022 NFT
+#[account]
+pub struct HorseNft {
+    pub mint: Pubkey,
+    pub horse: Horse,
+    pub minted_by: Pubkey,
+    pub minted_slot: u64,
+}
+
+/// A horse escrowed for sale; it is out of the seller's `Player` account
+/// (and so out of racing, breeding and release) until bought or cancelled
+#[account]
+pub struct HorseListing {
+    pub seller: Pubkey,
+    pub listing_id: u64,
+    pub horse: Horse,
+    pub price: u64,        // In `token_mint` base units
+    pub listed_slot: u64,
+}
+
+/// A discrete race: horses register during the entry window, then the podium
+/// is drawn from the first SlotHashes entry at or after
+/// `entry_close_slot + MIN_RANDOMNESS_DELAY_SLOTS`
+#[account]
+pub struct RaceEvent {
+    pub race_id: u64,
+    pub entry_close_slot: u64,
+    pub purse: u64,                 // Tokens reserved from the emission supply for the podium
+    pub entrant_count: u8,
+    pub entrants: [RaceEntrant; MAX_RACE_ENTRANTS as usize],
+    pub settled: bool,
+    pub podium_count: u8,
+    pub podium: [u8; RACE_PODIUM_SIZE], // Entrant indices in finishing order
+    pub prizes_claimed: [bool; RACE_PODIUM_SIZE],
+    pub cancelled: bool, // Set once the draw can no longer be proven; nothing is paid
+}
+
+impl RaceEvent {
+    pub fn find_entrant(&self, wallet: &Pubkey) -> Option<usize> {
+        self.entrants[..self.entrant_count as usize]
+            .iter()
+            .position(|entrant| entrant.wallet == *wallet)
+    }
+}
+
+/// Snapshot of a horse taken when it registers for a race
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct RaceEntrant {
+    pub wallet: Pubkey,
+    pub horse_id: u16,
+    pub speed: u16,
+    pub stamina_cost: u8,
+}
+
+/// Authority handoff awaiting acceptance by `proposed_authority`
+#[account]
+pub struct PendingAuthority {
+    pub proposed_authority: Pubkey,
+    pub proposed_by: Pubkey,
+    pub proposed_slot: u64,
+}
+
+/// A queued `update_config` waiting out `PARAMETER_TIMELOCK_SLOTS`
+#[account]
+pub struct PendingParameterChange {
+    pub update: ConfigUpdate,
+    pub queued_slot: u64,
+    pub effective_slot: u64,
+    pub queued_by: Pubkey, // Refunded the account rent when the update is applied
+}
+
+/// Typed `GlobalState` config change; `None` leaves a field untouched
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default, Debug)]
+pub struct ConfigUpdate {
+    pub fees_wallet: Option<Pubkey>,
+    pub burn_rate: Option<u8>,
+    pub referral_fee: Option<u8>,
+    pub sol_rewards_share: Option<u8>,
+    pub dust_threshold_divisor: Option<u64>,
+    pub initial_stable_purchase_fee_lamports: Option<u64>,
+    pub horse_pack_cost_microtokens: Option<u64>,
+    pub gamble_fee_lamports: Option<u64>,
+    pub staking_lockup_slots: Option<u64>,
+    pub token_reward_rate: Option<u64>,
+    pub gamble_win_chance_bps: Option<u16>,
+    pub gamble_house_edge_bps: Option<u16>,
+    pub marketplace_fee_bps: Option<u16>,
+    pub pity_b_threshold: Option<u16>,
+    pub pity_ss_threshold: Option<u16>,
+}
+
+/// Authority-set reward curve, measured from `GlobalState::start_slot`.
+/// Replaces the built-in stages once `emission_schedule_enabled` is set.
+#[account]
+pub struct EmissionSchedule {
+    pub mode: u8, // EMISSION_MODE_*
+    pub segment_count: u8,
+    pub segments: [EmissionSegment; MAX_EMISSION_SEGMENTS],
+    pub halving_initial_rate: u64,
+    pub halving_interval_slots: u64,
+    pub updated_slot: u64,
+}
+
+impl EmissionSchedule {
+    /// Reward rate in effect at `slot`
+    pub fn rate_at(&self, start_slot: u64, slot: u64) -> u64 {
+        match self.mode {
+            EMISSION_MODE_HALVING => halving_rate_at(
+                self.halving_initial_rate,
+                self.halving_interval_slots,
+                start_slot,
+                slot,
+            ),
+            _ => segment_rate_at(&self.segments[..self.segment_count as usize], start_slot, slot),
+        }
+    }
+
+    /// Total rewards emitted over the slot range `[from, to)`
+    pub fn emission_between(&self, start_slot: u64, from: u64, to: u64) -> u128 {
+        match self.mode {
+            EMISSION_MODE_HALVING => halving_emission(
+                self.halving_initial_rate,
+                self.halving_interval_slots,
+                start_slot,
+                from,
+                to,
+            ),
+            _ => segment_emission(
+                &self.segments[..self.segment_count as usize],
+                start_slot,
+                from,
+                to,
+            ),
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct EmissionSegment {
+    pub duration_slots: u64,
+    pub rate: u64, // Reward per slot
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default)]
 pub struct SpeedCheckpoint {
     pub slot: u64,
-    pub total_speed: u64,
-    pub accumulated_rewards: u64,
+    pub total_speed: u64,         // Speed that earned the rewards up to `slot`
+    pub accumulated_rewards: u64, // `cumulative_rewards` at `slot`
+}
+
+/// Ring buffer of pool snapshots taken by `update_pool` every `interval_slots`.
+/// Checkpoints live in a `Vec` (heap) since the full buffer is too large for the stack.
+#[account]
+pub struct SpeedHistory {
+    pub interval_slots: u64,
+    pub last_checkpoint_slot: u64,
+    pub head: u16, // Index the next checkpoint overwrites once the buffer is full
+    pub checkpoints: Vec<SpeedCheckpoint>, // At most SPEED_HISTORY_CAPACITY
+}
+
+impl SpeedHistory {
+    /// Appends a checkpoint once `interval_slots` have passed since the last one
+    pub fn record(&mut self, slot: u64, total_speed: u64, accumulated_rewards: u64) {
+        if self.interval_slots == 0
+            || (!self.checkpoints.is_empty()
+                && slot < self.last_checkpoint_slot.saturating_add(self.interval_slots))
+        {
+            return;
+        }
+
+        let checkpoint = SpeedCheckpoint {
+            slot,
+            total_speed,
+            accumulated_rewards,
+        };
+        if self.checkpoints.len() < SPEED_HISTORY_CAPACITY {
+            self.checkpoints.push(checkpoint);
+        } else {
+            self.checkpoints[self.head as usize] = checkpoint;
+            self.head = ((self.head as usize + 1) % SPEED_HISTORY_CAPACITY) as u16;
+        }
+        self.last_checkpoint_slot = slot;
+    }
 }
 